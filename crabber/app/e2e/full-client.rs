@@ -11,7 +11,7 @@ fn main() {
     Test {
         label: "Test full client".to_string(),
         setup: |app| {
-            app.add_plugin(CrabberClientPlugin)
+            app.add_plugin(CrabberClientPlugin::default())
                 .add_system(on_ready.in_schedule(OnEnter(AssetsState::Ready)))
                 .run();
         },
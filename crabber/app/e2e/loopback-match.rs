@@ -0,0 +1,286 @@
+use std::{collections::BTreeSet, thread, time::Duration};
+
+use bevy::{
+    app::App,
+    core::{FrameCountPlugin, TaskPoolPlugin, TypeRegistrationPlugin},
+    input::{keyboard::KeyboardInput, ButtonState, InputPlugin},
+    prelude::{Component, Entity, KeyCode, NextState, State, With, Without},
+    time::TimePlugin,
+};
+
+use loopback_transport::LoopbackNetwork;
+
+use crabber_app::{
+    components::{InterpolationOf, PredictionOf},
+    resources::{PlayerIdentity, RemoteCrabs, SocketFactory},
+    AppState, CrabberClientPlugin, PredictionDiagnostics,
+};
+use crabber_graphics::OwnCrab;
+use crabber_protocol::components::{Knockout, Lives, MatchState, PlayerName, Position};
+use crabber_server::{config::ListenSocket, CrabberServerPlugin};
+
+// how long each frame waits, so that the server and clients tick in real time
+const FRAME_DURATION: Duration = Duration::from_millis(4);
+// how many frames a step of the test may take before it is a failure (about a minute).
+// Naia keeps time with the system clock rather than bevy's `Time`, so this test depends on
+// real time: when a client's estimate of the server's clock drifts, its inputs arrive late
+// until it catches up, and a step can take many times longer than usual.
+const MAX_FRAMES: usize = 15000;
+
+const PLAYER_NAMES: [&str; 2] = ["Hermit", "Fiddler"];
+// the first client predicts the other player's crab, and the second interpolates it
+const REMOTE_CRABS: [RemoteCrabs; 2] = [RemoteCrabs::Predicted, RemoteCrabs::Interpolated];
+
+fn server_app(network: &LoopbackNetwork) -> App {
+    let mut app = App::new();
+    app.add_plugin(TaskPoolPlugin::default())
+        .add_plugin(TypeRegistrationPlugin)
+        .add_plugin(FrameCountPlugin)
+        .insert_resource(ListenSocket::new(network.server_socket()))
+        .add_plugin(CrabberServerPlugin::default());
+    app.setup();
+    app
+}
+
+// A client without a window or graphics, which connects as soon as it starts
+fn client_app(network: &LoopbackNetwork, name: &str, remote_crabs: RemoteCrabs) -> App {
+    let network = network.clone();
+    let mut app = App::new();
+    app.add_plugin(TaskPoolPlugin::default())
+        .add_plugin(TypeRegistrationPlugin)
+        .add_plugin(FrameCountPlugin)
+        .add_plugin(TimePlugin)
+        .add_plugin(InputPlugin)
+        .insert_resource(PlayerIdentity::new(name.to_string(), None))
+        .insert_resource(SocketFactory::new(move || network.client_socket()))
+        .insert_resource(remote_crabs)
+        .add_plugin(CrabberClientPlugin::default())
+        .insert_resource(NextState(Some(AppState::Connecting)));
+    app.setup();
+    app
+}
+
+fn client_state(app: &App) -> AppState {
+    app.world.resource::<State<AppState>>().0
+}
+
+// The names of the crabs that have been replicated to a client
+fn replicated_names(app: &mut App) -> BTreeSet<String> {
+    app.world
+        .query::<&PlayerName>()
+        .iter(&app.world)
+        .map(|name| (*name.name).clone())
+        .collect()
+}
+
+// The client's own crab, as it predicts it
+fn predicted_crab(app: &mut App) -> Entity {
+    app.world
+        .query_filtered::<Entity, (With<PredictionOf>, With<OwnCrab>)>()
+        .single(&app.world)
+}
+
+fn count<C: Component>(app: &mut App) -> usize {
+    app.world.query::<&C>().iter(&app.world).count()
+}
+
+// Where a client predicts another player's crab, and where the server last put that crab
+fn remote_predicted_crab(app: &mut App, name: &str) -> (f32, f32) {
+    let (position, PredictionOf(source), _) = app
+        .world
+        .query_filtered::<(&Position, &PredictionOf, &PlayerName), Without<OwnCrab>>()
+        .iter(&app.world)
+        .find(|(_, _, player_name)| *player_name.name == name)
+        .unwrap();
+    let source_position = app.world.get::<Position>(*source).unwrap();
+    (*position.y, *source_position.y)
+}
+
+// Where a client draws another player's crab, and where the server last put that crab
+fn interpolated_crab(app: &mut App) -> (f32, f32) {
+    let (position, InterpolationOf(source)) = app
+        .world
+        .query::<(&Position, &InterpolationOf)>()
+        .single(&app.world);
+    let source_position = app.world.get::<Position>(*source).unwrap();
+    (*position.y, *source_position.y)
+}
+
+fn press_key(app: &mut App, key_code: KeyCode, state: ButtonState) {
+    app.world.send_event(KeyboardInput {
+        scan_code: 0,
+        key_code: Some(key_code),
+        state,
+    });
+}
+
+// Steps the server and then every client, one frame at a time, until the check passes
+fn run_until(
+    label: &str,
+    server: &mut App,
+    clients: &mut [App],
+    mut check: impl FnMut(&mut [App]) -> bool,
+) {
+    for _ in 0..MAX_FRAMES {
+        server.update();
+        for client in clients.iter_mut() {
+            client.update();
+        }
+        if check(clients) {
+            println!("Passed: {}", label);
+            return;
+        }
+        thread::sleep(FRAME_DURATION);
+    }
+    panic!("Timed out waiting until {}", label);
+}
+
+fn main() {
+    println!("Running: Test a match over the loopback transport");
+    let network = LoopbackNetwork::new();
+    let mut server = server_app(&network);
+    let mut clients = PLAYER_NAMES
+        .iter()
+        .zip(REMOTE_CRABS)
+        .map(|(name, remote_crabs)| client_app(&network, name, remote_crabs))
+        .collect::<Vec<_>>();
+
+    run_until(
+        "every client is in the lobby",
+        &mut server,
+        &mut clients,
+        |clients| {
+            clients
+                .iter()
+                .all(|client| client_state(client) == AppState::Lobby)
+        },
+    );
+
+    for client in clients.iter_mut() {
+        press_key(client, KeyCode::Space, ButtonState::Pressed);
+    }
+    run_until(
+        "every client is in game",
+        &mut server,
+        &mut clients,
+        |clients| {
+            clients
+                .iter()
+                .all(|client| client_state(client) == AppState::InGame)
+        },
+    );
+    for client in clients.iter_mut() {
+        press_key(client, KeyCode::Space, ButtonState::Released);
+    }
+
+    let expected_names = PLAYER_NAMES
+        .iter()
+        .map(|name| name.to_string())
+        .collect::<BTreeSet<_>>();
+    run_until(
+        "every client sees every crab",
+        &mut server,
+        &mut clients,
+        |clients| {
+            clients
+                .iter_mut()
+                .all(|client| replicated_names(client) == expected_names)
+        },
+    );
+
+    run_until(
+        "every client predicts or interpolates the other crab",
+        &mut server,
+        &mut clients,
+        |clients| {
+            count::<PredictionOf>(&mut clients[0]) == 2
+                && count::<InterpolationOf>(&mut clients[0]) == 0
+                && count::<PredictionOf>(&mut clients[1]) == 1
+                && count::<InterpolationOf>(&mut clients[1]) == 1
+        },
+    );
+    let (_, start_y) = interpolated_crab(&mut clients[1]);
+    let (_, remote_start_y) = remote_predicted_crab(&mut clients[0], PLAYER_NAMES[1]);
+
+    // crabs stay put during the countdown, so moves only reach the server once the round starts
+    run_until(
+        "every client sees the round start",
+        &mut server,
+        &mut clients,
+        |clients| {
+            clients.iter_mut().all(|client| {
+                client
+                    .world
+                    .query::<&MatchState>()
+                    .iter(&client.world)
+                    .any(MatchState::is_playing)
+            })
+        },
+    );
+
+    // a knockout that the server never saw is undone once the crab's moves come back from it,
+    // along with the life that it cost
+    let prediction = predicted_crab(&mut clients[0]);
+    clients[0]
+        .world
+        .entity_mut(prediction)
+        .insert(Knockout::new());
+    press_key(&mut clients[0], KeyCode::W, ButtonState::Pressed);
+    run_until(
+        "the mispredicted knockout is undone",
+        &mut server,
+        &mut clients,
+        |clients| {
+            let crab = clients[0].world.entity(prediction);
+            let diagnostics = clients[0].world.resource::<PredictionDiagnostics>();
+            !crab.contains::<Knockout>()
+                && crab.get::<Lives>().is_some_and(|lives| *lives.lost == 0)
+                && diagnostics.mispredicted_components.contains_key("Knockout")
+        },
+    );
+    press_key(&mut clients[0], KeyCode::W, ButtonState::Released);
+
+    // the hop may end in a respawn, depending on the level, so it is enough that the crab moved
+    let mut has_moved = false;
+    run_until(
+        "the other client draws the crab where the server put it",
+        &mut server,
+        &mut clients,
+        |clients| {
+            let (drawn_y, server_y) = interpolated_crab(&mut clients[1]);
+            has_moved |= server_y != start_y;
+            has_moved && drawn_y == server_y
+        },
+    );
+
+    // the other player's moves are relayed to the first client ahead of the server's state,
+    // so that it predicts them as soon as they are made
+    let mut is_predicted_early = false;
+    press_key(&mut clients[1], KeyCode::W, ButtonState::Pressed);
+    run_until(
+        "the server moves the other player's crab",
+        &mut server,
+        &mut clients,
+        |clients| {
+            let (predicted_y, server_y) = remote_predicted_crab(&mut clients[0], PLAYER_NAMES[1]);
+            is_predicted_early |= server_y == remote_start_y && predicted_y != remote_start_y;
+            server_y != remote_start_y
+        },
+    );
+    press_key(&mut clients[1], KeyCode::W, ButtonState::Released);
+    assert!(
+        is_predicted_early,
+        "the other player's crab was only predicted to move once the server moved it"
+    );
+    println!("Passed: the other player's relayed moves are predicted early");
+
+    run_until(
+        "the first client predicts the other crab where the server put it",
+        &mut server,
+        &mut clients,
+        |clients| {
+            let (predicted_y, server_y) = remote_predicted_crab(&mut clients[0], PLAYER_NAMES[1]);
+            predicted_y == server_y
+        },
+    );
+}
@@ -1,7 +1,39 @@
-use bevy::prelude::{Component, Entity};
+use std::collections::VecDeque;
+
+use bevy::prelude::{Added, Component, Entity, Vec2, Without};
+
+use naia_bevy_client::Tick;
+
+use crabber_protocol::components::{Crab, Direction};
 
 #[derive(Component)]
 pub struct PredictionOf(pub Entity);
 
 #[derive(Component)]
 pub struct SourceOf(pub Entity);
+
+// A copy of another player's crab, which is drawn moving smoothly between the states that the
+// server sent for it, rather than jumping whenever one arrives
+#[derive(Component)]
+pub struct InterpolationOf(pub Entity);
+
+// The replicated crab that an `InterpolationOf` is drawn in place of
+#[derive(Component)]
+pub struct InterpolatedBy(pub Entity);
+
+// A crab that was just replicated from the server, rather than one of the copies drawn for it
+pub type NewReplicatedCrab = (
+    Added<Crab>,
+    Without<SourceOf>,
+    Without<PredictionOf>,
+    Without<InterpolationOf>,
+);
+
+// The positions that the server sent for an interpolated crab, along with the ticks they were
+// sent on, oldest first
+#[derive(Component, Default)]
+pub struct InterpolationBuffer(pub VecDeque<(Tick, Vec2, Direction)>);
+
+// Tracks the row up to which the client has built obstacles for a replicated level
+#[derive(Component)]
+pub struct BuiltObstacleRows(pub i16);
@@ -1,16 +1,73 @@
-use bevy::prelude::{info, EventReader, NextState, ResMut};
+use bevy::{
+    prelude::{info, warn, EventReader, NextState, Query, Res, ResMut, State, With},
+    window::{PrimaryWindow, Window},
+};
 
 use naia_bevy_client::{
     events::{ConnectEvent, DisconnectEvent, RejectEvent},
-    transport::webrtc,
     Client,
 };
 
-use crate::AppState;
+use crabber_protocol::{components::validate_player_name, messages::AuthMessage};
+
+use crate::{
+    resources::{PlayerIdentity, ServerUrl, SocketFactory},
+    AppState,
+};
+
+pub fn inititate_connection(
+    mut client: Client,
+    identity: Res<PlayerIdentity>,
+    server_url: Res<ServerUrl>,
+    socket_factory: Option<Res<SocketFactory>>,
+    mut state: ResMut<NextState<AppState>>,
+) {
+    // the server checks the name too, but cannot say why it turned one down
+    let name = match validate_player_name(&identity.name) {
+        Ok(name) => name,
+        Err(error) => {
+            warn!("Cannot connect as {:?}: {}", identity.name, error);
+            state.set(AppState::Disconnected);
+            return;
+        }
+    };
+    client.auth(AuthMessage::new(
+        name,
+        identity.token.clone(),
+        identity.session_token.clone(),
+    ));
+
+    if let Some(socket_factory) = socket_factory {
+        client.connect((socket_factory.0)());
+        return;
+    }
+    info!("Connecting to {}", server_url.0);
+    connect(&mut client, &server_url, &mut state);
+}
+
+#[cfg(not(feature = "transport_udp"))]
+fn connect(client: &mut Client, server_url: &ServerUrl, _state: &mut NextState<AppState>) {
+    use naia_bevy_client::transport::webrtc;
 
-pub fn inititate_connection(mut client: Client) {
-    // create a socket
-    let socket = webrtc::Socket::new("http://127.0.0.1:14191", client.socket_config());
+    let socket = webrtc::Socket::new(&server_url.0, client.socket_config());
+    client.connect(socket);
+}
+
+// Native clients can skip the WebRTC handshake, and send straight to the server's UDP address
+#[cfg(feature = "transport_udp")]
+fn connect(client: &mut Client, server_url: &ServerUrl, state: &mut NextState<AppState>) {
+    use naia_bevy_client::transport::udp;
+
+    let Ok(address) = server_url.0.parse() else {
+        warn!(
+            "Cannot connect to {}: expected an address, like 127.0.0.1:14193",
+            server_url.0
+        );
+        state.set(AppState::Disconnected);
+        return;
+    };
+    let link_condition = client.socket_config().link_condition.clone();
+    let socket = udp::Socket::new(&address, link_condition);
     client.connect(socket);
 }
 
@@ -21,27 +78,46 @@ pub fn connection_events(
 ) {
     for _event in event_reader.iter() {
         info!("Client connected to: {:?}", client.server_address());
-        // enter the "InGame" state
-        state.set(AppState::InGame);
+        // wait in the lobby until the match starts
+        state.set(AppState::Lobby);
     }
 }
 
 pub fn disconnection_events(
     client: Client,
+    identity: Res<PlayerIdentity>,
+    current_state: Res<State<AppState>>,
     mut state: ResMut<NextState<AppState>>,
     mut event_reader: EventReader<DisconnectEvent>,
 ) {
     for _event in event_reader.into_iter() {
         info!("Client disconnected from: {:?}", client.server_address());
-        // reset to loading state for now
-        // eventually, we could use this to show an alert supporting user actions
-        // or handle some sort of reconnection
-        state.set(AppState::Connecting);
+        // the server holds our crab for a while, so try to get back to it
+        if current_state.0 == AppState::InGame && identity.session_token.is_some() {
+            state.set(AppState::Reconnecting);
+        } else {
+            state.set(AppState::Connecting);
+        }
     }
 }
 
-pub fn rejection_events(mut event_reader: EventReader<RejectEvent>) {
+pub fn show_reconnecting(mut window_query: Query<&mut Window, With<PrimaryWindow>>) {
+    info!("Reconnecting to the server...");
+    if let Ok(mut window) = window_query.get_single_mut() {
+        window.title = "Crabber: reconnecting...".to_string();
+    }
+}
+
+pub fn rejection_events(
+    mut event_reader: EventReader<RejectEvent>,
+    identity: Res<PlayerIdentity>,
+    mut state: ResMut<NextState<AppState>>,
+) {
     for _ in event_reader.iter() {
-        info!("Client rejected from connecting to Server");
+        warn!(
+            "Client rejected from connecting to Server: {} may already be playing, or the token is wrong",
+            identity.name
+        );
+        state.set(AppState::Disconnected);
     }
 }
@@ -1,45 +1,124 @@
 use bevy::{
-    prelude::{Commands, EventReader},
-    sprite::SpriteSheetBundle,
+    prelude::{
+        warn, Commands, DespawnRecursiveExt, Entity, EventReader, NextState, Query, ResMut, With,
+    },
+    sprite::{SpriteSheetBundle, TextureAtlasSprite},
 };
 
 use naia_bevy_client::{
-    events::{InsertComponentEvents, MessageEvents},
+    events::{InsertComponentEvents, MessageEvents, RemoveComponentEvents, UpdateComponentEvents},
     Client, CommandsExt,
 };
 
 use crabber_controller::components::Controller;
+use crabber_graphics::{CameraTarget, OwnCrab};
 use crabber_protocol::{
-    channels::PlayerAssignmentChannel,
-    components::{Car, Controlled, Raft},
-    messages::PlayerAssignmentMessage,
+    channels::{LevelChecksumChannel, PlayerAssignmentChannel},
+    components::{
+        obstacle_checksum, ConstantMotor, Controlled, Level, MotorOrigin, OnLevel, TileRow,
+    },
+    constants::LEVEL_GENERATOR_VERSION,
+    messages::{LevelChecksumMessage, PlayerAssignmentMessage},
 };
 
-use crate::components::{PredictionOf, SourceOf};
+use crate::{
+    components::{BuiltObstacleRows, NewReplicatedCrab, PredictionOf, SourceOf},
+    resources::PlayerIdentity,
+    AppState,
+};
+
+fn spawn_level_obstacles(
+    commands: &mut Commands,
+    level_entity: Entity,
+    level: &Level,
+    start_row: TileRow,
+) {
+    let (car_bundles, raft_bundles) = level.create_rows_bundles(start_row, level.end_row());
+    for bundle in car_bundles.into_iter() {
+        commands.spawn((bundle, OnLevel(level_entity), Controlled));
+    }
+    for bundle in raft_bundles.into_iter() {
+        commands.spawn((bundle, OnLevel(level_entity), Controlled));
+    }
+    commands
+        .entity(level_entity)
+        .insert(BuiltObstacleRows(level.end_row().0));
+}
+
+// Predicts a replicated crab on a duplicate of it, which is drawn in its place
+fn spawn_prediction(commands: &mut Commands, entity: Entity) -> Entity {
+    let prediction_entity = commands
+        .entity(entity)
+        .duplicate() // create a new entity and copy all `Replicate`
+        .insert((PredictionOf(entity), Controlled)) // this is the prediction entity
+        .id();
+
+    commands
+        .entity(entity)
+        .remove::<SpriteSheetBundle>() // no need to show graphics for this
+        .insert(SourceOf(prediction_entity)); // this is the original source entity
+    prediction_entity
+}
 
 pub fn receive_entity_assignment_message(
     mut event_reader: EventReader<MessageEvents>,
     mut commands: Commands,
     client: Client,
+    mut identity: ResMut<PlayerIdentity>,
+    mut state: ResMut<NextState<AppState>>,
+    sources_query: Query<&SourceOf>,
 ) {
     for event in event_reader.iter() {
         for assignment in event.read::<PlayerAssignmentChannel, PlayerAssignmentMessage>() {
+            identity.session_token = Some(assignment.session_token);
             let entity = assignment.entity.get(&client).unwrap();
-            let prediction_entity = commands
-                .entity(entity)
-                .duplicate() // create a new entity and copy all `Replicate`
-                .insert((PredictionOf(entity), Controlled)) // this is the prediction entity
-                .id();
-
+            // the crab may already be predicted, along with every other player's
+            let prediction_entity = match sources_query.get(entity) {
+                Ok(SourceOf(prediction_entity)) => *prediction_entity,
+                Err(_) => spawn_prediction(&mut commands, entity),
+            };
             commands
-                .entity(entity)
-                .remove::<SpriteSheetBundle>() // no need to show graphics for this
-                .insert((
-                    // this is the original source entity
-                    SourceOf(prediction_entity),
-                    // attach controls to this one so tick system can easily retrieve them
-                    Controller::keyboard(0),
-                ));
+                .entity(prediction_entity)
+                .insert((CameraTarget, OwnCrab));
+            // attach controls to the source so tick system can easily retrieve them
+            commands.entity(entity).insert(Controller::keyboard(0));
+
+            // the match has started
+            state.set(AppState::InGame);
+        }
+    }
+}
+
+// Predicts the crabs of other players too, from the inputs that the server relays
+pub fn spawn_remote_predictions(
+    mut commands: Commands,
+    crab_query: Query<Entity, NewReplicatedCrab>,
+) {
+    for entity in crab_query.iter() {
+        spawn_prediction(&mut commands, entity);
+    }
+}
+
+// The predictions are drawn in place of the replicated crabs, which the graphics set up like any
+// other
+pub fn hide_predicted_sources(
+    mut commands: Commands,
+    source_query: Query<Entity, (With<SourceOf>, With<TextureAtlasSprite>)>,
+) {
+    for entity in source_query.iter() {
+        commands.entity(entity).remove::<SpriteSheetBundle>();
+    }
+}
+
+// Despawns the predictions of crabs that the server has despawned, such as when a round ends
+pub fn despawn_orphaned_predictions(
+    mut commands: Commands,
+    prediction_query: Query<(Entity, &PredictionOf)>,
+    source_query: Query<(), With<SourceOf>>,
+) {
+    for (entity, PredictionOf(source)) in prediction_query.iter() {
+        if !source_query.contains(*source) {
+            commands.entity(entity).despawn_recursive();
         }
     }
 }
@@ -47,25 +126,86 @@ pub fn receive_entity_assignment_message(
 pub fn receive_insert_component_events(
     mut commands: Commands,
     mut event_reader: EventReader<InsertComponentEvents>,
+    level_query: Query<&Level>,
 ) {
     for event in event_reader.iter() {
-        for entity in event.read::<Raft>() {
-            let prediction_entity = commands
-                .entity(entity)
-                .duplicate()
-                .insert((PredictionOf(entity), Controlled))
-                .id();
+        for entity in event.read::<Level>() {
+            let Ok(level) = level_query.get(entity) else { continue };
+            if *level.generator_version != LEVEL_GENERATOR_VERSION {
+                warn!(
+                    "Level was generated with version {}, but this client uses version {}",
+                    *level.generator_version, LEVEL_GENERATOR_VERSION,
+                );
+            }
+            // obstacles are not replicated, so we rebuild them from the level seed
+            spawn_level_obstacles(&mut commands, entity, level, TileRow(*level.first_row));
+        }
+    }
+}
 
-            commands.entity(entity).insert(SourceOf(prediction_entity));
+pub fn receive_update_component_events(
+    mut commands: Commands,
+    mut event_reader: EventReader<UpdateComponentEvents>,
+    level_query: Query<(&Level, &BuiltObstacleRows)>,
+) {
+    for event in event_reader.iter() {
+        for (_tick, entity) in event.read::<Level>() {
+            let Ok((level, BuiltObstacleRows(built_row))) = level_query.get(entity) else { continue };
+            // endless levels generate new rows as crabs climb
+            if level.end_row().0 > *built_row {
+                let start_row = TileRow((*built_row).max(*level.first_row));
+                spawn_level_obstacles(&mut commands, entity, level, start_row);
+            }
         }
-        for entity in event.read::<Car>() {
-            let prediction_entity = commands
-                .entity(entity)
-                .duplicate()
-                .insert((PredictionOf(entity), Controlled))
-                .id();
+    }
+}
 
-            commands.entity(entity).insert(SourceOf(prediction_entity));
+pub fn receive_remove_component_events(
+    mut commands: Commands,
+    mut event_reader: EventReader<RemoveComponentEvents>,
+    obstacles_query: Query<(Entity, &OnLevel)>,
+) {
+    for event in event_reader.iter() {
+        for (level_entity, _) in event.read::<Level>() {
+            for (entity, OnLevel(obstacle_level)) in obstacles_query.iter() {
+                if *obstacle_level == level_entity {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+        }
+    }
+}
+
+pub fn receive_level_checksum_message(
+    mut event_reader: EventReader<MessageEvents>,
+    client: Client,
+    level_query: Query<(&Level, &BuiltObstacleRows)>,
+    obstacles_query: Query<(&ConstantMotor, &MotorOrigin, &OnLevel)>,
+) {
+    for event in event_reader.iter() {
+        for message in event.read::<LevelChecksumChannel, LevelChecksumMessage>() {
+            let Some(level_entity) = message.level.get(&client) else { continue };
+            let Ok((level, BuiltObstacleRows(built_row))) = level_query.get(level_entity) else { continue };
+            // skip the check if we have not caught up to the rows the server has checked
+            if *level.first_row > message.first_row || *built_row < message.end_row {
+                continue;
+            }
+            let obstacles = obstacles_query
+                .iter()
+                .filter(|(_, origin, OnLevel(obstacle_level))| {
+                    let TileRow(row) = level.y_to_row(origin.y);
+                    *obstacle_level == level_entity
+                        && row >= message.first_row
+                        && row < message.end_row
+                })
+                .map(|(motor, origin, _)| (motor, origin));
+            let checksum = obstacle_checksum(obstacles, level, message.tick);
+            if checksum != message.checksum {
+                warn!(
+                    "Level obstacles diverged from the server at tick {}",
+                    message.tick
+                );
+            }
         }
     }
 }
@@ -0,0 +1,257 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    ecs::component::Component,
+    prelude::{Commands, DespawnRecursiveExt, Entity, EventReader, Query, Res, Vec2, With},
+    sprite::{SpriteSheetBundle, TextureAtlasSprite},
+    utils::HashMap,
+};
+
+use naia_bevy_client::{
+    events::{InsertComponentEvents, RemoveComponentEvents, UpdateComponentEvents},
+    sequence_greater_than, Client, Replicate, Tick,
+};
+
+use crabber_protocol::components::{Crab, Direction, Knockout, PlayerName, Position, StepMotor};
+
+use crate::{
+    components::{
+        InterpolatedBy, InterpolationBuffer, InterpolationOf, NewReplicatedCrab, SourceOf,
+    },
+    resources::InterpolationConfig,
+};
+
+// how many states are kept for each crab, which is far more than are ever waiting to be drawn
+const MAX_BUFFERED_STATES: usize = 64;
+
+type State = (Tick, Vec2, Direction);
+
+fn copy_component<C: Replicate + Component>(component: &C) -> Option<C> {
+    let component = component
+        .copy_to_box()
+        .to_boxed_any()
+        .downcast::<C>()
+        .ok()?;
+    Some(*component)
+}
+
+// When a tick happened, in ticks before the latest server tick,
+// which keeps the arithmetic simple when tick numbers wrap around
+fn tick_time(tick: Tick, server_tick: Tick) -> f32 {
+    -(server_tick.wrapping_sub(tick) as i16 as f32)
+}
+
+// Draws the crabs that other players control through copies of them, which are interpolated
+#[allow(clippy::type_complexity)]
+pub fn spawn_interpolations(
+    mut commands: Commands,
+    client: Client,
+    crab_query: Query<
+        (
+            Entity,
+            &Position,
+            Option<&PlayerName>,
+            Option<&StepMotor>,
+            Option<&Knockout>,
+        ),
+        NewReplicatedCrab,
+    >,
+) {
+    let Some(server_tick) = client.server_tick() else { return };
+    for (entity, position, player_name, motor, knockout) in crab_query.iter() {
+        let (x, y, direction) = (*position.x, *position.y, *position.direction);
+        let mut interpolation = commands.spawn((
+            Crab,
+            Position::new(x, y, direction),
+            InterpolationOf(entity),
+            InterpolationBuffer(VecDeque::from([(server_tick, Vec2::new(x, y), direction)])),
+        ));
+        // along with the parts of the crab that are drawn straight from its latest state
+        if let Some(player_name) = player_name.and_then(copy_component) {
+            interpolation.insert(player_name);
+        }
+        if let Some(motor) = motor.and_then(copy_component) {
+            interpolation.insert(motor);
+        }
+        if let Some(knockout) = knockout.and_then(copy_component) {
+            interpolation.insert(knockout);
+        }
+        let interpolation = interpolation.id();
+        commands
+            .entity(entity)
+            .insert(InterpolatedBy(interpolation));
+    }
+}
+
+// The copies are drawn in place of the replicated crabs, which the graphics set up like any other
+pub fn hide_interpolated_sources(
+    mut commands: Commands,
+    source_query: Query<Entity, (With<InterpolatedBy>, With<TextureAtlasSprite>)>,
+) {
+    for entity in source_query.iter() {
+        commands.entity(entity).remove::<SpriteSheetBundle>();
+    }
+}
+
+// Despawns the copies of crabs that the server has despawned, and of crabs that turned out to be
+// this player's once the assignment arrived, since those are predicted instead
+pub fn despawn_orphaned_interpolations(
+    mut commands: Commands,
+    interpolation_query: Query<(Entity, &InterpolationOf)>,
+    source_query: Query<Option<&SourceOf>, With<InterpolatedBy>>,
+) {
+    for (entity, InterpolationOf(source)) in interpolation_query.iter() {
+        match source_query.get(*source) {
+            Ok(None) => continue,
+            Ok(Some(_)) => {
+                commands.entity(*source).remove::<InterpolatedBy>();
+            }
+            Err(_) => {}
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Keeps a component of the copies in step with the server, for the parts of crabs that are drawn
+// straight from their latest state
+pub fn mirror_server_changes<C: Replicate + Component>(
+    mut commands: Commands,
+    mut insert_reader: EventReader<InsertComponentEvents>,
+    mut update_reader: EventReader<UpdateComponentEvents>,
+    mut remove_reader: EventReader<RemoveComponentEvents>,
+    source_query: Query<(&InterpolatedBy, Option<&C>)>,
+) {
+    let inserted = insert_reader.iter().flat_map(|events| events.read::<C>());
+    let updated = update_reader
+        .iter()
+        .flat_map(|events| events.read::<C>())
+        .map(|(_, entity)| entity);
+    for entity in inserted.chain(updated) {
+        let Ok((InterpolatedBy(interpolation), Some(component))) = source_query.get(entity) else {
+            continue;
+        };
+        if let Some(component) = copy_component(component) {
+            commands.entity(*interpolation).insert(component);
+        }
+    }
+    for events in remove_reader.iter() {
+        for (entity, _) in events.read::<C>() {
+            if let Ok((InterpolatedBy(interpolation), _)) = source_query.get(entity) {
+                commands.entity(*interpolation).remove::<C>();
+            }
+        }
+    }
+}
+
+// Adds the positions that the server sent to the buffers of the copies
+pub fn buffer_server_positions(
+    mut update_reader: EventReader<UpdateComponentEvents>,
+    source_query: Query<(&Position, &InterpolatedBy)>,
+    mut buffer_query: Query<&mut InterpolationBuffer>,
+) {
+    // only the latest of the updates received this frame is still on the crab,
+    // so it is the only one that can be buffered
+    let mut latest_ticks = HashMap::<Entity, Tick>::default();
+    for events in update_reader.iter() {
+        for (server_tick, entity) in events.read::<Position>() {
+            let latest_tick = latest_ticks.entry(entity).or_insert(server_tick);
+            if sequence_greater_than(server_tick, *latest_tick) {
+                *latest_tick = server_tick;
+            }
+        }
+    }
+    for (entity, server_tick) in latest_ticks {
+        let Ok((position, InterpolatedBy(interpolation))) = source_query.get(entity) else {
+            continue;
+        };
+        let Ok(mut buffer) = buffer_query.get_mut(*interpolation) else { continue };
+        let states = &mut buffer.0;
+        if states
+            .back()
+            .is_some_and(|(last_tick, _, _)| !sequence_greater_than(server_tick, *last_tick))
+        {
+            continue;
+        }
+        states.push_back((
+            server_tick,
+            Vec2::new(*position.x, *position.y),
+            *position.direction,
+        ));
+        if states.len() > MAX_BUFFERED_STATES {
+            states.pop_front();
+        }
+    }
+}
+
+// Works out where a crab was at `render_time`, forgetting the states that are no longer needed.
+// Past its latest state, a crab that is still hopping keeps going for a little while.
+fn sample_states(
+    states: &mut VecDeque<State>,
+    server_tick: Tick,
+    render_time: f32,
+    is_hopping: bool,
+    config: &InterpolationConfig,
+) -> Option<(Vec2, Direction)> {
+    while states.len() > 2 && tick_time(states[1].0, server_tick) <= render_time {
+        states.pop_front();
+    }
+    let (last_tick, last_position, last_direction) = *states.back()?;
+    let last_time = tick_time(last_tick, server_tick);
+
+    if states.len() == 1 || render_time >= last_time {
+        // the state after the latest one has not arrived yet
+        let extrapolation_ticks = render_time - last_time;
+        if extrapolation_ticks > config.max_extrapolation_ticks {
+            // the crab has stayed put, so only its latest state is needed from now on
+            states.drain(..states.len() - 1);
+        }
+        let (previous_tick, previous_position, _) = states[0];
+        if !is_hopping
+            || previous_tick == last_tick
+            || previous_position.distance(last_position) > config.max_distance
+        {
+            return Some((last_position, last_direction));
+        }
+        let velocity = (last_position - previous_position)
+            / (last_time - tick_time(previous_tick, server_tick));
+        let extrapolated = velocity * extrapolation_ticks.min(config.max_extrapolation_ticks);
+        return Some((last_position + extrapolated, last_direction));
+    }
+
+    let (from_tick, from_position, from_direction) = states[0];
+    let (to_tick, to_position, to_direction) = states[1];
+    let from_time = tick_time(from_tick, server_tick);
+    if render_time <= from_time || from_position.distance(to_position) > config.max_distance {
+        return Some((from_position, from_direction));
+    }
+    let fraction = (render_time - from_time) / (tick_time(to_tick, server_tick) - from_time);
+    Some((from_position.lerp(to_position, fraction), to_direction))
+}
+
+// Moves the copies to where their crabs were a little while ago, between the states around then
+pub fn interpolate_positions(
+    client: Client,
+    config: Res<InterpolationConfig>,
+    mut interpolation_query: Query<
+        (&mut InterpolationBuffer, &mut Position, Option<&StepMotor>),
+        With<InterpolationOf>,
+    >,
+) {
+    let (Some(server_tick), Some(interpolation)) =
+        (client.server_tick(), client.server_interpolation())
+    else {
+        return;
+    };
+    let render_time = interpolation - config.delay_ticks;
+    for (mut buffer, mut position, motor) in interpolation_query.iter_mut() {
+        let is_hopping = motor.is_some_and(|motor| motor.step.is_some());
+        let Some((sampled, direction)) =
+            sample_states(&mut buffer.0, server_tick, render_time, is_hopping, &config)
+        else {
+            continue;
+        };
+        *position.x = sampled.x;
+        *position.y = sampled.y;
+        *position.direction = direction;
+    }
+}
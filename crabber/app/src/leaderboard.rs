@@ -0,0 +1,57 @@
+use bevy::prelude::{info, EventReader, Input, KeyCode, Local, Query, Res};
+
+use naia_bevy_client::{events::MessageEvents, Client};
+
+use crabber_protocol::{
+    channels::LeaderboardChannel,
+    components::{MatchPhase, MatchState},
+    messages::{LeaderboardRequestMessage, LeaderboardResponseMessage},
+};
+
+use crate::results::ticks_to_seconds;
+
+// Asks for the current level's top scores when L is pressed, and whenever a round's results
+// are shown, which is after the round has been saved
+pub fn request_leaderboard(
+    mut client: Client,
+    mut requested_round: Local<Option<u16>>,
+    keys: Res<Input<KeyCode>>,
+    match_query: Query<&MatchState>,
+) {
+    let results_round = match_query
+        .get_single()
+        .ok()
+        .filter(|match_state| *match_state.phase == MatchPhase::Results)
+        .map(|match_state| *match_state.round);
+    let has_new_results = results_round.is_some() && results_round != *requested_round;
+    if has_new_results {
+        *requested_round = results_round;
+    }
+    if has_new_results || keys.just_pressed(KeyCode::L) {
+        client.send_message::<LeaderboardChannel, LeaderboardRequestMessage>(
+            &LeaderboardRequestMessage::new(None),
+        );
+    }
+}
+
+pub fn receive_leaderboard_response(mut event_reader: EventReader<MessageEvents>) {
+    for event in event_reader.iter() {
+        for message in event.read::<LeaderboardChannel, LeaderboardResponseMessage>() {
+            if message.entries.is_empty() {
+                info!("Leaderboard {}: no scores yet", message.leaderboard);
+                continue;
+            }
+            info!("Leaderboard {}:", message.leaderboard);
+            for (index, entry) in message.entries.iter().enumerate() {
+                info!(
+                    "{:>2}. {} {} points in {}s (seed {})",
+                    index + 1,
+                    entry.name,
+                    entry.score,
+                    ticks_to_seconds(entry.ticks),
+                    entry.seed
+                );
+            }
+        }
+    }
+}
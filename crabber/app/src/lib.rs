@@ -1,19 +1,37 @@
-use bevy::prelude::{
-    App, IntoSystemAppConfig, IntoSystemConfigs, IntoSystemSetConfig, OnEnter, Plugin, States,
-    SystemSet,
+use bevy::{
+    ecs::schedule::Condition,
+    prelude::{
+        apply_system_buffers, in_state, resource_equals, App, IntoSystemAppConfig,
+        IntoSystemAppConfigs, IntoSystemConfig, IntoSystemConfigs, IntoSystemSetConfig, OnEnter,
+        OnExit, Plugin, States, SystemSet,
+    },
 };
 
 use naia_bevy_client::{ClientConfig, Plugin as ClientPlugin, ReceiveEvents};
 
 use crabber_controller::ControllerPlugin;
-use crabber_core::TickPlugin;
-use crabber_protocol::protocol;
+use crabber_core::{CorePostTickSchedule, TickPlugin};
+use crabber_graphics::{CorrectionSmoothing, DebugOverlay, StatusPanel};
+use crabber_protocol::{
+    components::{Knockout, Lives, PlayerName, Position, Score, StepMotor},
+    link_condition::LinkCondition,
+    protocol,
+};
+
+use rollback::RollbackAppExt;
+pub use rollback::PredictionDiagnostics;
 
 pub mod components;
 mod connection;
 mod events;
+mod interpolation;
+mod leaderboard;
+mod lobby;
+mod overlay;
 pub mod resources;
+mod results;
 mod rollback;
+mod spectator;
 mod tick;
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, States)]
@@ -21,6 +39,9 @@ pub enum AppState {
     #[default]
     Waiting, // not yet ready
     Connecting,   // connecting to game
+    Reconnecting, // connecting again after losing the connection mid-match
+    Lobby,        // waiting for players to ready up
+    Spectating,   // watching a match without a crab
     InGame,       // in game actively
     Disconnected, // disconnected
 }
@@ -31,23 +52,97 @@ struct TickSet;
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, SystemSet)]
 struct RollbackSet;
 
-pub struct CrabberClientPlugin;
+#[derive(Default)]
+pub struct CrabberClientPlugin {
+    // the network conditions simulated on packets from the server
+    pub link_condition: LinkCondition,
+}
+
+impl CrabberClientPlugin {
+    pub fn new(link_condition: LinkCondition) -> Self {
+        CrabberClientPlugin { link_condition }
+    }
+}
 
 impl Plugin for CrabberClientPlugin {
     fn build(&self, app: &mut App) {
+        let mut protocol = protocol();
+        self.link_condition.apply(&mut protocol);
         app.add_state::<AppState>()
             .configure_set(TickSet.in_set(ReceiveEvents))
             .configure_set(RollbackSet.after(TickSet).in_set(ReceiveEvents))
             .init_resource::<resources::TickHistory>()
-            .add_plugin(ClientPlugin::new(ClientConfig::default(), protocol()))
+            .init_resource::<resources::RemoteCrabs>()
+            .init_resource::<resources::InterpolationConfig>()
+            .init_resource::<resources::PlayerIdentity>()
+            .init_resource::<resources::ServerUrl>()
+            .init_resource::<lobby::LobbyState>()
+            .init_resource::<spectator::SpectatorCamera>()
+            .insert_resource(self.link_condition)
+            .init_resource::<DebugOverlay>()
+            .init_resource::<StatusPanel>()
+            .init_resource::<CorrectionSmoothing>()
+            .init_resource::<rollback::PendingCorrections>()
+            .add_plugin(ClientPlugin::new(ClientConfig::default(), protocol))
             .add_plugin(TickPlugin::new(TickSet, tick::send_and_prepare_inputs))
-            .add_plugin(TickPlugin::new(
-                RollbackSet,
-                rollback::receive_update_component_events,
-            ))
+            .add_plugin(TickPlugin::new(RollbackSet, rollback::rollback_predictions))
+            // the components that ticks change on crabs, which predictions are checked against
+            .add_rollback_component::<Position>()
+            .add_rollback_component::<StepMotor>()
+            .add_rollback_component::<Knockout>()
+            .add_rollback_component::<Score>()
+            .add_rollback_component::<Lives>()
+            .add_system(rollback::record_predictions.in_schedule(CorePostTickSchedule))
+            // other players' crabs are either predicted like the player's own, from the inputs
+            // that the server relays, or drawn between the states the server sent for them
+            .add_systems(
+                (
+                    apply_system_buffers,
+                    events::spawn_remote_predictions
+                        .run_if(resource_equals(resources::RemoteCrabs::Predicted)),
+                    events::hide_predicted_sources,
+                )
+                    .chain()
+                    .in_set(ReceiveEvents)
+                    .after(events::receive_entity_assignment_message)
+                    .before(TickSet),
+            )
+            .add_system(
+                tick::receive_relayed_inputs
+                    .in_set(ReceiveEvents)
+                    .after(TickSet)
+                    .before(RollbackSet),
+            )
+            .add_systems(
+                (
+                    interpolation::spawn_interpolations
+                        .run_if(resource_equals(resources::RemoteCrabs::Interpolated)),
+                    interpolation::hide_interpolated_sources,
+                    interpolation::despawn_orphaned_interpolations,
+                    interpolation::mirror_server_changes::<PlayerName>,
+                    interpolation::mirror_server_changes::<StepMotor>,
+                    interpolation::mirror_server_changes::<Knockout>,
+                    interpolation::buffer_server_positions,
+                    interpolation::interpolate_positions
+                        .after(interpolation::buffer_server_positions),
+                )
+                    .in_set(ReceiveEvents),
+            )
+            .add_system(
+                rollback::smooth_corrections
+                    .in_set(ReceiveEvents)
+                    .after(RollbackSet),
+            )
             .add_plugin(ControllerPlugin)
             // try to initiate a connection once we enter the "InGame" state
             .add_system(connection::inititate_connection.in_schedule(OnEnter(AppState::Connecting)))
+            .add_systems(
+                (
+                    connection::show_reconnecting,
+                    connection::inititate_connection,
+                )
+                    .in_schedule(OnEnter(AppState::Reconnecting)),
+            )
             // react to any connection, disconnection, rejection events from server
             .add_systems(
                 (
@@ -56,9 +151,55 @@ impl Plugin for CrabberClientPlugin {
                     connection::rejection_events,
                     events::receive_entity_assignment_message,
                     events::receive_insert_component_events,
+                    events::receive_update_component_events,
+                    events::receive_remove_component_events,
+                    events::receive_level_checksum_message,
+                    events::despawn_orphaned_predictions,
+                )
+                    .in_set(ReceiveEvents)
+                    .before(TickSet),
+            )
+            .add_systems(
+                (
+                    lobby::receive_lobby_state_message,
+                    lobby::toggle_ready.run_if(in_state(AppState::Lobby)),
+                    lobby::update_lobby_window_title.run_if(in_state(AppState::Lobby)),
+                    lobby::update_lobby_panel.run_if(in_state(AppState::Lobby)),
                 )
+                    .chain()
                     .in_set(ReceiveEvents)
                     .before(TickSet),
+            )
+            .add_system(lobby::leave_lobby.in_schedule(OnExit(AppState::Lobby)))
+            .add_system(
+                spectator::receive_spectator_assignment_message
+                    .in_set(ReceiveEvents)
+                    .after(connection::connection_events),
+            )
+            .add_systems(
+                (spectator::cycle_camera_target, spectator::pan_free_camera)
+                    .chain()
+                    .distributive_run_if(in_state(AppState::Spectating)),
+            )
+            .add_system(spectator::leave_spectating.in_schedule(OnExit(AppState::Spectating)))
+            .add_system(
+                results::show_match_state
+                    .run_if(in_state(AppState::InGame).or_else(in_state(AppState::Spectating))),
+            )
+            .add_systems(
+                (
+                    leaderboard::request_leaderboard
+                        .run_if(in_state(AppState::InGame).or_else(in_state(AppState::Spectating))),
+                    leaderboard::receive_leaderboard_response,
+                )
+                    .in_set(ReceiveEvents),
+            )
+            .add_systems(
+                (
+                    overlay::toggle_network_overlay,
+                    overlay::update_network_overlay,
+                )
+                    .chain(),
             );
     }
 }
@@ -0,0 +1,118 @@
+use bevy::{
+    prelude::{
+        info, DetectChanges, EventReader, Input, KeyCode, Query, Res, ResMut, Resource, With,
+    },
+    window::{PrimaryWindow, Window},
+};
+
+use naia_bevy_client::{events::MessageEvents, Client};
+
+use crabber_graphics::StatusPanel;
+
+use crabber_protocol::{
+    channels::LobbyChannel,
+    messages::{LobbyPlayer, LobbyStateMessage, ReadyMessage},
+};
+
+const DEFAULT_WINDOW_TITLE: &str = "Crabber";
+
+// What the client knows about the lobby it is waiting in
+#[derive(Resource, Default)]
+pub struct LobbyState {
+    pub players: Vec<LobbyPlayer>,
+    pub countdown_ticks: Option<u16>,
+    // whether this client has readied up
+    pub ready: bool,
+}
+
+pub fn receive_lobby_state_message(
+    mut event_reader: EventReader<MessageEvents>,
+    mut lobby: ResMut<LobbyState>,
+) {
+    for event in event_reader.iter() {
+        for message in event.read::<LobbyChannel, LobbyStateMessage>() {
+            let ready_count = message.players.iter().filter(|player| player.ready).count();
+            info!(
+                "Lobby: {}/{} players ready",
+                ready_count,
+                message.players.len()
+            );
+            lobby.players = message.players;
+            lobby.countdown_ticks = message.countdown_ticks;
+        }
+    }
+}
+
+pub fn toggle_ready(mut client: Client, keys: Res<Input<KeyCode>>, mut lobby: ResMut<LobbyState>) {
+    if keys.just_pressed(KeyCode::Space) || keys.just_pressed(KeyCode::Return) {
+        lobby.ready = !lobby.ready;
+        client.send_message::<LobbyChannel, ReadyMessage>(&ReadyMessage::new(lobby.ready));
+    }
+}
+
+pub fn update_lobby_window_title(
+    lobby: Res<LobbyState>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if !lobby.is_changed() {
+        return;
+    }
+    let Ok(mut window) = window_query.get_single_mut() else { return };
+    let ready_count = lobby.players.iter().filter(|player| player.ready).count();
+    let prompt = if lobby.ready {
+        "press Space to unready"
+    } else {
+        "press Space to ready up"
+    };
+    window.title = match lobby.countdown_ticks {
+        Some(ticks) => format!(
+            "{} lobby: {}/{} ready, starting in {}s, {}",
+            DEFAULT_WINDOW_TITLE,
+            ready_count,
+            lobby.players.len(),
+            ticks / 60,
+            prompt,
+        ),
+        None => format!(
+            "{} lobby: {}/{} ready, {}",
+            DEFAULT_WINDOW_TITLE,
+            ready_count,
+            lobby.players.len(),
+            prompt,
+        ),
+    };
+}
+
+// Lists everyone in the lobby and whether they are ready, since the title only fits a summary
+pub fn update_lobby_panel(lobby: Res<LobbyState>, mut panel: ResMut<StatusPanel>) {
+    if !lobby.is_changed() {
+        return;
+    }
+    let mut text = String::from("Lobby\n");
+    for player in lobby.players.iter() {
+        let state = if player.ready { "ready" } else { "not ready" };
+        text.push_str(&format!("{}: {}\n", player.name, state));
+    }
+    if let Some(ticks) = lobby.countdown_ticks {
+        text.push_str(&format!("starting in {}s\n", ticks / 60));
+    }
+    text.push_str(if lobby.ready {
+        "press Space to unready"
+    } else {
+        "press Space to ready up"
+    });
+    panel.visible = true;
+    panel.text = text;
+}
+
+pub fn leave_lobby(
+    mut lobby: ResMut<LobbyState>,
+    mut panel: ResMut<StatusPanel>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    *lobby = LobbyState::default();
+    *panel = StatusPanel::default();
+    if let Ok(mut window) = window_query.get_single_mut() {
+        window.title = DEFAULT_WINDOW_TITLE.to_string();
+    }
+}
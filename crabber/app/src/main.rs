@@ -3,19 +3,124 @@ use bevy::{
     DefaultPlugins,
 };
 
-use crabber_graphics::{AssetsState, GraphicsPlugin};
+use crabber_graphics::{AssetsState, CorrectionSmoothing, GraphicsPlugin};
 
-use crabber_app::{AppState, CrabberClientPlugin};
+use crabber_app::{
+    resources::{PlayerIdentity, RemoteCrabs, ServerUrl},
+    AppState, CrabberClientPlugin,
+};
+use crabber_protocol::link_condition::LinkCondition;
 
 fn on_ready(mut state: ResMut<NextState<AppState>>) {
     state.set(AppState::Connecting);
 }
 
+fn exit_with_error(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+fn read_value(args: &mut impl Iterator<Item = String>, flag: &str, expected: &str) -> String {
+    match args.next() {
+        Some(value) => value,
+        None => exit_with_error(format!("{} requires {}", flag, expected)),
+    }
+}
+
+fn read_link_condition(args: &mut impl Iterator<Item = String>, flag: &str) -> LinkCondition {
+    match args.next().map(|value| value.parse::<LinkCondition>()) {
+        Some(Ok(link_condition)) => link_condition,
+        Some(Err(error)) => exit_with_error(format!("{}: {}", flag, error)),
+        None => exit_with_error(format!("{} requires a preset name, like poor", flag)),
+    }
+}
+
+// Reads how long corrections are eased in for, in milliseconds, or `off` to snap to them
+fn read_smoothing(args: &mut impl Iterator<Item = String>, flag: &str) -> CorrectionSmoothing {
+    let value = read_value(args, flag, "a half-life in milliseconds, or off");
+    if value == "off" {
+        return CorrectionSmoothing {
+            enabled: false,
+            ..Default::default()
+        };
+    }
+    match value.parse::<f32>() {
+        Ok(half_life_ms) if half_life_ms >= 0. => CorrectionSmoothing {
+            half_life: half_life_ms / 1000.,
+            ..Default::default()
+        },
+        _ => exit_with_error(format!(
+            "{} requires a half-life in milliseconds, or off",
+            flag
+        )),
+    }
+}
+
+fn read_remote_crabs(args: &mut impl Iterator<Item = String>, flag: &str) -> RemoteCrabs {
+    match read_value(args, flag, "predicted or interpolated").as_str() {
+        "predicted" => RemoteCrabs::Predicted,
+        "interpolated" => RemoteCrabs::Interpolated,
+        _ => exit_with_error(format!("{} requires predicted or interpolated", flag)),
+    }
+}
+
+// Reads the command line, where `--server <url>` sets the server's signaling address, or its UDP
+// address when built with `transport_udp` (defaulting to `CRABBER_SERVER_URL`, or a local server),
+// and `--name <name>` and `--token <token>` set who to connect as (defaulting to `CRABBER_NAME`
+// and `CRABBER_TOKEN`).
+// `--link-condition <preset or latency_ms,jitter_ms,loss>` simulates a good, average, poor or
+// perfect network on packets from the server (defaulting to `CRABBER_LINK_CONDITION`, or good).
+// `--smoothing <half_life_ms or off>` sets how quickly sprites catch up with corrected predictions.
+// `--remote-crabs <predicted or interpolated>` sets how other players' crabs are drawn.
+fn read_args() -> (
+    ServerUrl,
+    PlayerIdentity,
+    LinkCondition,
+    CorrectionSmoothing,
+    RemoteCrabs,
+) {
+    let mut server_url = ServerUrl::default();
+    let mut smoothing = CorrectionSmoothing::default();
+    let mut remote_crabs = RemoteCrabs::default();
+    let mut identity = PlayerIdentity::from_env();
+    let mut link_condition = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--server" => server_url = ServerUrl(read_value(&mut args, &arg, "a url")),
+            "--name" => identity.name = read_value(&mut args, &arg, "a name"),
+            "--token" => identity.token = Some(read_value(&mut args, &arg, "a token")),
+            "--link-condition" => link_condition = Some(read_link_condition(&mut args, &arg)),
+            "--smoothing" => smoothing = read_smoothing(&mut args, &arg),
+            "--remote-crabs" => remote_crabs = read_remote_crabs(&mut args, &arg),
+            _ => exit_with_error(format!("Unknown argument: {}", arg)),
+        }
+    }
+    let link_condition = link_condition.unwrap_or_else(|| {
+        LinkCondition::from_env().unwrap_or_else(|error| {
+            exit_with_error(format!("Failed to read link condition: {}", error))
+        })
+    });
+    (
+        server_url,
+        identity,
+        link_condition,
+        smoothing,
+        remote_crabs,
+    )
+}
+
 fn main() {
+    let (server_url, identity, link_condition, smoothing, remote_crabs) = read_args();
+
     let mut app = App::default();
     app.add_plugins(DefaultPlugins)
+        .insert_resource(server_url)
+        .insert_resource(identity)
+        .insert_resource(smoothing)
+        .insert_resource(remote_crabs)
         .add_plugin(GraphicsPlugin)
-        .add_plugin(CrabberClientPlugin)
+        .add_plugin(CrabberClientPlugin::new(link_condition))
         .add_system(on_ready.in_schedule(OnEnter(AssetsState::Ready)))
         .run();
 }
@@ -0,0 +1,53 @@
+use bevy::prelude::{Input, KeyCode, Res, ResMut};
+
+use naia_bevy_client::Client;
+
+use crabber_graphics::DebugOverlay;
+use crabber_protocol::link_condition::LinkCondition;
+
+use crate::rollback::PredictionDiagnostics;
+
+const TOGGLE_OVERLAY_KEY: KeyCode = KeyCode::F3;
+
+pub fn toggle_network_overlay(keys: Res<Input<KeyCode>>, mut overlay: ResMut<DebugOverlay>) {
+    if keys.just_pressed(TOGGLE_OVERLAY_KEY) {
+        overlay.visible = !overlay.visible;
+    }
+}
+
+// Shows how the connection is doing, along with the conditions being simulated on it
+// and how often predictions have had to be corrected.
+// Naia does not count lost packets, so only the simulated loss is shown.
+pub fn update_network_overlay(
+    client: Client,
+    link_condition: Res<LinkCondition>,
+    diagnostics: Res<PredictionDiagnostics>,
+    mut overlay: ResMut<DebugOverlay>,
+) {
+    if !overlay.visible {
+        return;
+    }
+    let connection = if client.is_connected() {
+        format!(
+            "RTT {:.0} ms, jitter {:.0} ms",
+            client.rtt(),
+            client.jitter()
+        )
+    } else {
+        "not connected".to_string()
+    };
+    let text = format!(
+        "{}\nsimulated link: {}\nmispredicted {} of {} ticks, last correction {:.0} px\n\
+         rolled back {} times for late inputs",
+        connection,
+        *link_condition,
+        diagnostics.mispredicted_ticks,
+        diagnostics.checked_ticks,
+        diagnostics.last_correction_distance,
+        diagnostics.late_input_rollbacks
+    );
+    // only write when something changed, so that the text is not laid out again every frame
+    if overlay.text != text {
+        overlay.text = text;
+    }
+}
@@ -1,7 +1,189 @@
-use bevy::prelude::Resource;
+use std::collections::VecDeque;
 
-use crabber_core::EntityActionMap;
-use naia_bevy_client::CommandHistory;
+use bevy::prelude::{Entity, Resource};
 
+use rand::Rng;
+
+use crabber_core::{EntityActionMap, TickActions};
+use crabber_protocol::{constants::TILE_SIZE_F32, inputs::InputAction};
+use naia_bevy_client::{sequence_greater_than, transport::Socket, Tick};
+
+// The actions taken on each tick that has been predicted, but not yet confirmed by the server,
+// which are replayed after rolling back
 #[derive(Resource, Default)]
-pub struct TickHistory(pub CommandHistory<EntityActionMap>);
+pub struct TickHistory {
+    // oldest first
+    ticks: VecDeque<TickActions>,
+    // the latest tick predicted, which is kept after it has been confirmed
+    last_tick: Option<Tick>,
+    // other players' actions that arrived before their ticks were predicted
+    upcoming: Vec<(Tick, Entity, InputAction)>,
+}
+
+impl TickHistory {
+    // Ticks are predicted in order, so each one is newer than the last. Returns the actions to
+    // predict the tick with, which include the other players' actions that arrived for it early.
+    pub fn insert(&mut self, tick: Tick, mut actions: EntityActionMap) -> EntityActionMap {
+        if self
+            .last_tick
+            .is_some_and(|last_tick| !sequence_greater_than(tick, last_tick))
+        {
+            return actions;
+        }
+        for (upcoming_tick, entity, action) in self.upcoming.iter() {
+            if *upcoming_tick == tick {
+                actions.0.entry(*entity).or_insert(*action);
+            }
+        }
+        self.upcoming
+            .retain(|(upcoming_tick, _, _)| sequence_greater_than(*upcoming_tick, tick));
+        self.ticks.push_back((tick, actions.clone()));
+        self.last_tick = Some(tick);
+        actions
+    }
+
+    // Adds another player's action to the tick that it was taken on, returning whether that tick
+    // has already been predicted. Ticks that are no longer kept have been confirmed by the server
+    // along with the action.
+    pub fn insert_relayed(&mut self, tick: Tick, entity: Entity, action: InputAction) -> bool {
+        if self
+            .last_tick
+            .is_none_or(|last_tick| sequence_greater_than(tick, last_tick))
+        {
+            self.upcoming.push((tick, entity, action));
+            return false;
+        }
+        let Some((_, actions)) = self
+            .ticks
+            .iter_mut()
+            .find(|(predicted_tick, _)| *predicted_tick == tick)
+        else {
+            return false;
+        };
+        actions.0.insert(entity, action);
+        true
+    }
+
+    // Forgets the ticks up to and including the server's tick
+    pub fn confirm(&mut self, server_tick: Tick) {
+        while self
+            .ticks
+            .front()
+            .is_some_and(|(tick, _)| !sequence_greater_than(*tick, server_tick))
+        {
+            self.ticks.pop_front();
+        }
+    }
+
+    // The ticks that are still to be confirmed, oldest first
+    pub fn replays(&self) -> Vec<TickActions> {
+        self.ticks.iter().cloned().collect()
+    }
+}
+
+// Whether other players' crabs are predicted from their relayed inputs, which keeps them in step
+// with the player's own crab, or interpolated between the states that the server sent for them,
+// which is smoother but shows them a little in the past
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RemoteCrabs {
+    #[default]
+    Predicted,
+    Interpolated,
+}
+
+// How other players' crabs are drawn between the states that the server sends for them
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct InterpolationConfig {
+    // how far behind the latest server tick crabs are drawn, so that the state after the one being
+    // drawn has usually arrived already, in ticks
+    pub delay_ticks: f32,
+    // how far past the latest state a hopping crab may be drawn, when the next one is late
+    pub max_extrapolation_ticks: f32,
+    // crabs that move further than this in one update, such as when they respawn,
+    // jump there rather than sliding across the level, in pixels
+    pub max_distance: f32,
+}
+
+impl Default for InterpolationConfig {
+    fn default() -> Self {
+        InterpolationConfig {
+            delay_ticks: 2.,
+            max_extrapolation_ticks: 2.,
+            max_distance: TILE_SIZE_F32,
+        }
+    }
+}
+
+// Where the client connects when no server is given, which is a server running locally
+#[cfg(not(feature = "transport_udp"))]
+pub const DEFAULT_SERVER_URL: &str = "http://127.0.0.1:14191";
+// Over UDP the client skips signaling, and sends straight to the server's UDP address
+#[cfg(feature = "transport_udp")]
+pub const DEFAULT_SERVER_URL: &str = "127.0.0.1:14193";
+// Local setups read the server's signaling address from this environment variable
+pub const SERVER_URL_ENV_VAR: &str = "CRABBER_SERVER_URL";
+
+// The server's signaling address, where the client starts its connection
+#[derive(Resource)]
+pub struct ServerUrl(pub String);
+
+impl Default for ServerUrl {
+    fn default() -> Self {
+        ServerUrl(
+            std::env::var(SERVER_URL_ENV_VAR).unwrap_or_else(|_| DEFAULT_SERVER_URL.to_string()),
+        )
+    }
+}
+
+// Makes the socket the client connects with, in place of the one for `ServerUrl`, which lets tests
+// run the client without a network. A new socket is made for every connection attempt.
+#[derive(Resource)]
+pub struct SocketFactory(pub Box<dyn Fn() -> Box<dyn Socket> + Send + Sync>);
+
+impl SocketFactory {
+    pub fn new<S: Into<Box<dyn Socket>>>(
+        make_socket: impl Fn() -> S + Send + Sync + 'static,
+    ) -> Self {
+        SocketFactory(Box::new(move || make_socket().into()))
+    }
+}
+
+// Local setups read the player's name from this environment variable
+pub const NAME_ENV_VAR: &str = "CRABBER_NAME";
+// and the server's token, if it asks for one, from this one
+pub const TOKEN_ENV_VAR: &str = "CRABBER_TOKEN";
+
+// Who the player is, which is sent to the server when connecting
+#[derive(Resource)]
+pub struct PlayerIdentity {
+    pub name: String,
+    pub token: Option<String>,
+    // given out by the server along with a crab, so the same crab can be taken back after
+    // a dropped connection
+    pub session_token: Option<String>,
+}
+
+impl PlayerIdentity {
+    pub fn new(name: String, token: Option<String>) -> Self {
+        PlayerIdentity {
+            name,
+            token,
+            session_token: None,
+        }
+    }
+
+    // Reads the identity from the environment, making up a name if none is set
+    pub fn from_env() -> Self {
+        let name = std::env::var(NAME_ENV_VAR).unwrap_or_else(|_| {
+            let number = rand::thread_rng().gen_range(1..1000);
+            format!("Crab {}", number)
+        });
+        PlayerIdentity::new(name, std::env::var(TOKEN_ENV_VAR).ok())
+    }
+}
+
+impl Default for PlayerIdentity {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
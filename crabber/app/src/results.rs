@@ -0,0 +1,154 @@
+use bevy::{
+    prelude::{info, Local, Query, ResMut, With, Without},
+    window::{PrimaryWindow, Window},
+};
+
+use crabber_controller::components::Controller;
+use crabber_graphics::StatusPanel;
+use crabber_protocol::components::{
+    Crab, Lives, MatchPhase, MatchState, PlayerName, RespawnConfig, RespawnRule, Score, Winner,
+};
+
+use crate::components::{InterpolationOf, PredictionOf};
+
+// The crabs replicated from the server, rather than the copies that are drawn in their place.
+// Only the player's own crab has a controller.
+type ReplicatedCrab = (With<Crab>, Without<PredictionOf>, Without<InterpolationOf>);
+// A row of the results panel
+type CrabScore<'a> = (
+    &'a PlayerName,
+    &'a Score,
+    Option<&'a Winner>,
+    Option<&'a Controller>,
+);
+
+// convert ticks to whole seconds for display, rounding up
+pub(crate) fn ticks_to_seconds(ticks: u16) -> u16 {
+    ticks.div_ceil(60)
+}
+
+fn describe_winner(
+    winner_query: &Query<Option<&Controller>, (ReplicatedCrab, With<Winner>)>,
+) -> &'static str {
+    match winner_query.iter().next() {
+        Some(Some(_)) => "you won",
+        Some(None) => "another crab won",
+        None => "nobody won",
+    }
+}
+
+fn describe_score(score: &Score) -> String {
+    format!(
+        "your score {} (rows {}, finish {}, time {}, penalty -{})",
+        *score.value, *score.row_points, *score.finish_bonus, *score.time_bonus, *score.penalty
+    )
+}
+
+// Lists every player's score, best first, for the results panel
+fn describe_results(
+    round: u16,
+    phase: MatchPhase,
+    seconds: u16,
+    winner_query: &Query<Option<&Controller>, (ReplicatedCrab, With<Winner>)>,
+    scores_query: &Query<CrabScore, ReplicatedCrab>,
+) -> String {
+    let mut scores = scores_query.iter().collect::<Vec<_>>();
+    scores.sort_by_key(|(_, score, _, _)| std::cmp::Reverse(*score.value));
+
+    let mut text = format!("Round {} over, {}\n", round, describe_winner(winner_query));
+    for (name, score, winner, controller) in scores {
+        text.push_str(&format!(
+            "{}{}: {} (rows {}, finish {}, time {}, penalty -{}){}\n",
+            *name.name,
+            if controller.is_some() { " (you)" } else { "" },
+            *score.value,
+            *score.row_points,
+            *score.finish_bonus,
+            *score.time_bonus,
+            *score.penalty,
+            if winner.is_some() { ", winner" } else { "" },
+        ));
+    }
+    if phase == MatchPhase::Results {
+        text.push_str(&format!("next round in {}s", seconds));
+    }
+    text
+}
+
+// Shows the state of the current round in the window title, with every player's score in a
+// panel once the round is over, and logs each phase as it starts
+pub fn show_match_state(
+    mut last_phase: Local<Option<(u16, MatchPhase)>>,
+    match_query: Query<(&MatchState, Option<&RespawnRule>)>,
+    winner_query: Query<Option<&Controller>, (ReplicatedCrab, With<Winner>)>,
+    player_query: Query<(&Lives, &Score), (ReplicatedCrab, With<Controller>)>,
+    scores_query: Query<CrabScore, ReplicatedCrab>,
+    mut panel: ResMut<StatusPanel>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let Ok((match_state, rule)) = match_query.get_single() else { return };
+    let round = *match_state.round;
+    let seconds = ticks_to_seconds(*match_state.phase_ticks);
+    let config = rule.map_or(RespawnConfig::default(), |rule| *rule.config);
+    let player = player_query.get_single().ok();
+    let lives_left = player.and_then(|(lives, _)| lives.remaining(&config));
+    let title = match *match_state.phase {
+        MatchPhase::Countdown => format!("Crabber: round {} starts in {}s", round, seconds),
+        MatchPhase::Playing => match lives_left {
+            Some(lives_left) => format!("Crabber: round {}, {} lives left", round, lives_left),
+            None => format!("Crabber: round {}", round),
+        },
+        MatchPhase::RoundOver => format!(
+            "Crabber: round {} over, {}",
+            round,
+            describe_winner(&winner_query)
+        ),
+        MatchPhase::Results => match player {
+            Some((_, score)) => format!(
+                "Crabber: round {} results, {}, {}, next round in {}s",
+                round,
+                describe_winner(&winner_query),
+                describe_score(score),
+                seconds
+            ),
+            None => format!(
+                "Crabber: round {} results, {}, next round in {}s",
+                round,
+                describe_winner(&winner_query),
+                seconds
+            ),
+        },
+    };
+
+    if *last_phase != Some((round, *match_state.phase)) {
+        *last_phase = Some((round, *match_state.phase));
+        info!("{}", title);
+    }
+    if let Ok(mut window) = window_query.get_single_mut() {
+        if window.title != title {
+            window.title = title;
+        }
+    }
+
+    match *match_state.phase {
+        MatchPhase::RoundOver | MatchPhase::Results => {
+            let text = describe_results(
+                round,
+                *match_state.phase,
+                seconds,
+                &winner_query,
+                &scores_query,
+            );
+            // only write when something changed, so that the text is not laid out again every frame
+            if !panel.visible || panel.text != text {
+                panel.visible = true;
+                panel.text = text;
+            }
+        }
+        MatchPhase::Countdown | MatchPhase::Playing => {
+            if panel.visible {
+                *panel = StatusPanel::default();
+            }
+        }
+    }
+}
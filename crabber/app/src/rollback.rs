@@ -1,59 +1,470 @@
-use bevy::prelude::{Entity, EventReader, Query, ResMut, With, Without};
-
-use crabber_core::TickActions;
-use naia_bevy_client::{events::UpdateComponentEvents, sequence_greater_than, Replicate, Tick};
-
-use crabber_protocol::components::{Controlled, Crab, Knockout, Position, StepMotor};
-
-use crate::{components::SourceOf, resources::TickHistory};
-
-pub fn receive_update_component_events(
-    mut event_reader: EventReader<UpdateComponentEvents>,
-    mut tick_history: ResMut<TickHistory>,
-    source_player_query: Query<
-        (&Position, &StepMotor, &SourceOf),
-        (With<Crab>, Without<Controlled>),
-    >,
-    source_objects_query: Query<(&Position, &SourceOf), (Without<Crab>, Without<Controlled>)>,
-    mut player_query: Query<
-        (Entity, &mut Position, &mut StepMotor),
-        (With<Crab>, Without<Knockout>, With<Controlled>),
-    >,
-    mut objects_query: Query<&mut Position, (Without<Crab>, With<Controlled>)>,
-) -> Vec<TickActions> {
-    // We only care about whatever the latest tick is
-    // so we check the events for the latest tick count,
-    // and use that to get the commands we need to replay
-    let mut latest_tick: Option<Tick> = None;
-    for events in event_reader.iter() {
-        for (server_tick, _entity) in events.read::<Position>() {
-            if let Some(last_tick) = latest_tick {
-                if sequence_greater_than(server_tick, last_tick) {
-                    latest_tick = Some(server_tick);
-                }
-            } else {
-                latest_tick = Some(server_tick);
+use std::{any::type_name, collections::VecDeque};
+
+use bevy::{
+    ecs::component::Component,
+    prelude::{
+        App, Commands, Entity, EventReader, IntoSystemConfig, Query, Res, ResMut, Resource, Vec2,
+        With, World,
+    },
+    utils::HashMap,
+};
+
+use crabber_core::{CurrentTick, TickActions};
+use crabber_graphics::{CorrectionOffset, CorrectionSmoothing};
+use naia_bevy_client::{
+    events::{InsertComponentEvents, RemoveComponentEvents, UpdateComponentEvents},
+    sequence_greater_than, Client, ReceiveEvents, Replicate, Tick,
+};
+
+use crabber_protocol::{
+    components::{Knockout, Lives, Position, Score, StepMotor},
+    constants::TILE_SIZE_F32,
+};
+
+use crate::{components::SourceOf, resources::TickHistory, RollbackSet};
+
+// how many ticks of predictions are kept while waiting for the server to confirm them,
+// which is about 4 seconds
+const MAX_PREDICTION_HISTORY_TICKS: usize = 256;
+// how far a predicted crab may be from the server's before it is corrected, in pixels
+const POSITION_TOLERANCE: f32 = 0.5;
+
+// How far a prediction of a component is from the server's state of it
+pub trait PredictionError {
+    // predictions that are further off than this are rolled back
+    const TOLERANCE: f32 = 0.;
+
+    fn prediction_error(&self, server: &Self) -> f32;
+}
+
+fn mismatch<T: PartialEq>(predicted: T, server: T) -> f32 {
+    if predicted == server {
+        0.
+    } else {
+        1.
+    }
+}
+
+impl PredictionError for Position {
+    const TOLERANCE: f32 = POSITION_TOLERANCE;
+
+    // facing the wrong way counts as being a whole tile off
+    fn prediction_error(&self, server: &Self) -> f32 {
+        if *self.direction != *server.direction {
+            return TILE_SIZE_F32;
+        }
+        (*self.x - *server.x).hypot(*self.y - *server.y)
+    }
+}
+
+impl PredictionError for StepMotor {
+    fn prediction_error(&self, server: &Self) -> f32 {
+        mismatch(*self.step, *server.step)
+    }
+}
+
+impl PredictionError for Knockout {
+    fn prediction_error(&self, server: &Self) -> f32 {
+        mismatch(*self.elapsed_ticks, *server.elapsed_ticks)
+    }
+}
+
+impl PredictionError for Lives {
+    fn prediction_error(&self, server: &Self) -> f32 {
+        mismatch(*self.lost, *server.lost)
+    }
+}
+
+// the rest of the score is only settled when the crab finishes, so it is not compared
+impl PredictionError for Score {
+    fn prediction_error(&self, server: &Self) -> f32 {
+        mismatch(
+            (*self.value, *self.best_row),
+            (*server.value, *server.best_row),
+        )
+    }
+}
+
+// A copy of a component as it was on an entity, or `None` if the entity did not have it
+type Snapshot = Option<Box<dyn Replicate>>;
+
+// How to save, check and reset one kind of component on predictions
+struct RollbackComponent {
+    name: &'static str,
+    snapshot: fn(&World, Entity) -> Snapshot,
+    // whether a snapshot of a prediction is too far from the server's state on its source entity
+    diverges: fn(&World, Entity, &Snapshot) -> bool,
+    // copies the server's state from a source entity onto its prediction
+    restore: fn(&mut World, Entity, Entity),
+}
+
+// The replicated components that predictions are checked against, and reset to when they roll back
+#[derive(Resource, Default)]
+pub struct RollbackComponents {
+    components: Vec<RollbackComponent>,
+}
+
+// What has arrived since predictions were last checked
+#[derive(Resource, Default)]
+pub struct PendingCheck {
+    // the latest server tick received for predicted entities, which predictions are checked against
+    pub server_tick: Option<Tick>,
+    // whether other players' inputs arrived for ticks that were already predicted without them,
+    // which must be replayed even if nothing has been mispredicted yet
+    pub has_late_inputs: bool,
+}
+
+impl PendingCheck {
+    fn request(&mut self, server_tick: Tick) {
+        if self
+            .server_tick
+            .is_none_or(|pending_tick| sequence_greater_than(server_tick, pending_tick))
+        {
+            self.server_tick = Some(server_tick);
+        }
+    }
+}
+
+// What each prediction looked like at the end of each tick, keyed by the source entity that it
+// predicts, until the server's state for that tick arrives
+#[derive(Resource, Default)]
+pub struct PredictionHistory {
+    // oldest first
+    ticks: VecDeque<(Tick, HashMap<Entity, Vec<Snapshot>>)>,
+}
+
+impl PredictionHistory {
+    // Replayed ticks replace what was first predicted for them
+    fn record(&mut self, tick: Tick, snapshots: HashMap<Entity, Vec<Snapshot>>) {
+        if let Some((_, recorded)) = self
+            .ticks
+            .iter_mut()
+            .find(|(recorded_tick, _)| *recorded_tick == tick)
+        {
+            *recorded = snapshots;
+            return;
+        }
+        self.ticks.push_back((tick, snapshots));
+        if self.ticks.len() > MAX_PREDICTION_HISTORY_TICKS {
+            self.ticks.pop_front();
+        }
+    }
+
+    // Takes what was predicted for a tick, forgetting it along with every tick before it
+    fn take(&mut self, tick: Tick) -> Option<HashMap<Entity, Vec<Snapshot>>> {
+        let mut taken = None;
+        while let Some((recorded_tick, _)) = self.ticks.front() {
+            if sequence_greater_than(*recorded_tick, tick) {
+                break;
+            }
+            let (recorded_tick, snapshots) = self.ticks.pop_front()?;
+            if recorded_tick == tick {
+                taken = Some(snapshots);
+            }
+        }
+        taken
+    }
+}
+
+// Where predictions were drawn before the latest rollback moved them
+#[derive(Resource, Default)]
+pub struct PendingCorrections(Vec<(Entity, Vec2)>);
+
+// How well the client has been predicting the server, for tracking down the causes of corrections
+#[derive(Resource, Default, Clone, Debug)]
+pub struct PredictionDiagnostics {
+    // server states that predictions were checked against
+    pub checked_ticks: u32,
+    // checks that found a prediction further off than its tolerance, and so rolled back
+    pub mispredicted_ticks: u32,
+    // how many checks found each component mispredicted
+    pub mispredicted_components: HashMap<&'static str, u32>,
+    // server states for ticks that had not been predicted, such as just after joining,
+    // which are rolled back to without being checked
+    pub unpredicted_ticks: u32,
+    // rollbacks to replay other players' inputs, which arrive after their ticks were predicted
+    pub late_input_rollbacks: u32,
+    // ticks simulated again after rolling back
+    pub replayed_ticks: u32,
+    // how far mispredicted crabs were from the server's, in pixels
+    pub last_correction_distance: f32,
+    pub max_correction_distance: f32,
+    pub total_correction_distance: f32,
+}
+
+impl PredictionDiagnostics {
+    fn record_correction(&mut self, distance: f32) {
+        self.last_correction_distance = distance;
+        self.max_correction_distance = self.max_correction_distance.max(distance);
+        self.total_correction_distance += distance;
+    }
+}
+
+pub trait RollbackAppExt {
+    // Checks the component on predictions against its server state, and resets it when they roll
+    // back, inserting or removing it so that the prediction has it only when the server does
+    fn add_rollback_component<C: Replicate + Component + PredictionError>(&mut self) -> &mut Self;
+}
+
+impl RollbackAppExt for App {
+    fn add_rollback_component<C: Replicate + Component + PredictionError>(&mut self) -> &mut Self {
+        self.init_resource::<RollbackComponents>()
+            .init_resource::<PendingCheck>()
+            .init_resource::<PredictionHistory>()
+            .init_resource::<PredictionDiagnostics>()
+            .add_system(
+                detect_server_changes::<C>
+                    .in_set(ReceiveEvents)
+                    .before(RollbackSet),
+            );
+        let name = type_name::<C>().rsplit("::").next().unwrap_or_default();
+        self.world
+            .resource_mut::<RollbackComponents>()
+            .components
+            .push(RollbackComponent {
+                name,
+                snapshot: snapshot_component::<C>,
+                diverges: component_diverges::<C>,
+                restore: restore_component::<C>,
+            });
+        self
+    }
+}
+
+// Asks for predictions to be checked when the server changes the component on a predicted entity
+fn detect_server_changes<C: Replicate + Component>(
+    client: Client,
+    mut insert_reader: EventReader<InsertComponentEvents>,
+    mut update_reader: EventReader<UpdateComponentEvents>,
+    mut remove_reader: EventReader<RemoveComponentEvents>,
+    mut pending_check: ResMut<PendingCheck>,
+    source_query: Query<(), With<SourceOf>>,
+) {
+    for events in update_reader.iter() {
+        for (server_tick, entity) in events.read::<C>() {
+            if source_query.contains(entity) {
+                pending_check.request(server_tick);
             }
         }
     }
-    if let Some(latest_tick) = latest_tick {
-        // Reset all expected entities to their source states
-        for (source_position, source_motor, SourceOf(prediction)) in source_player_query.iter() {
-            if let Ok((_, mut position, mut motor)) = player_query.get_mut(*prediction) {
-                position.mirror(source_position);
-                motor.mirror(source_motor);
+    let mut inserted_or_removed = false;
+    for events in insert_reader.iter() {
+        let inserted = events.read::<C>();
+        inserted_or_removed |= inserted
+            .into_iter()
+            .any(|entity| source_query.contains(entity));
+    }
+    for events in remove_reader.iter() {
+        let removed = events.read::<C>();
+        inserted_or_removed |= removed
+            .iter()
+            .any(|(entity, _)| source_query.contains(*entity));
+    }
+    // insertions and removals do not say which tick they happened on,
+    // so they are checked against the latest tick received from the server
+    if inserted_or_removed {
+        if let Some(server_tick) = client.server_tick() {
+            pending_check.request(server_tick);
+        }
+    }
+}
+
+fn snapshot_component<C: Replicate + Component>(world: &World, entity: Entity) -> Snapshot {
+    world
+        .get::<C>(entity)
+        .map(|component| component.copy_to_box())
+}
+
+fn component_diverges<C: Replicate + Component + PredictionError>(
+    world: &World,
+    source: Entity,
+    snapshot: &Snapshot,
+) -> bool {
+    let predicted = snapshot
+        .as_ref()
+        .and_then(|snapshot| snapshot.to_any().downcast_ref::<C>());
+    match (predicted, world.get::<C>(source)) {
+        (Some(predicted), Some(server)) => predicted.prediction_error(server) > C::TOLERANCE,
+        (None, None) => false,
+        // only one of the prediction and the server has the component
+        _ => true,
+    }
+}
+
+fn restore_component<C: Replicate + Component>(
+    world: &mut World,
+    source: Entity,
+    prediction: Entity,
+) {
+    let snapshot = snapshot_component::<C>(world, source);
+    let Some(mut prediction) = world.get_entity_mut(prediction) else { return };
+    match (snapshot, prediction.get_mut::<C>()) {
+        (Some(snapshot), Some(mut component)) => component.mirror(snapshot.as_ref()),
+        (Some(snapshot), None) => {
+            if let Ok(component) = snapshot.to_boxed_any().downcast::<C>() {
+                prediction.insert(*component);
             }
         }
-        for (source_position, SourceOf(prediction)) in source_objects_query.iter() {
-            if let Ok(mut position) = objects_query.get_mut(*prediction) {
-                position.mirror(source_position);
+        (None, Some(_)) => {
+            prediction.remove::<C>();
+        }
+        (None, None) => {}
+    }
+}
+
+// Each source entity that the server replicates, along with its prediction
+fn predicted_entities(world: &mut World) -> Vec<(Entity, Entity)> {
+    world
+        .query::<(Entity, &SourceOf)>()
+        .iter(world)
+        .map(|(source, SourceOf(prediction))| (source, *prediction))
+        .collect()
+}
+
+// Saves what every prediction looks like at the end of the tick that was just simulated
+pub fn record_predictions(world: &mut World) {
+    let tick = world.resource::<CurrentTick>().0;
+    let predictions = predicted_entities(world);
+    let rollback_components = world.resource::<RollbackComponents>();
+    let snapshots = predictions
+        .into_iter()
+        .map(|(source, prediction)| {
+            let snapshots = rollback_components
+                .components
+                .iter()
+                .map(|component| (component.snapshot)(world, prediction))
+                .collect();
+            (source, snapshots)
+        })
+        .collect();
+    world
+        .resource_mut::<PredictionHistory>()
+        .record(tick, snapshots);
+}
+
+// Checks what was predicted for a server tick against what the server sent for it,
+// returning whether anything was mispredicted
+fn check_predictions(
+    world: &World,
+    predicted: Option<HashMap<Entity, Vec<Snapshot>>>,
+    predictions: &[(Entity, Entity)],
+    diagnostics: &mut PredictionDiagnostics,
+) -> bool {
+    let Some(predicted) = predicted else {
+        diagnostics.unpredicted_ticks += 1;
+        return true;
+    };
+    let rollback_components = world.resource::<RollbackComponents>();
+    diagnostics.checked_ticks += 1;
+    let mut is_mispredicted = false;
+    let mut correction_distance: f32 = 0.;
+    for (source, _) in predictions.iter() {
+        // crabs that appeared since the tick was predicted are checked from the next one
+        let Some(snapshots) = predicted.get(source) else { continue };
+        let components = rollback_components.components.iter().zip(snapshots);
+        for (component, snapshot) in components {
+            if (component.diverges)(world, *source, snapshot) {
+                is_mispredicted = true;
+                *diagnostics
+                    .mispredicted_components
+                    .entry(component.name)
+                    .or_default() += 1;
             }
         }
-        // Then replay ticks
-        let mut replays = tick_history.0.replays(&latest_tick);
-        replays.reverse();
-        replays
+        let predicted_position = snapshots
+            .iter()
+            .flatten()
+            .find_map(|snapshot| snapshot.to_any().downcast_ref::<Position>());
+        if let (Some(predicted_position), Some(server_position)) =
+            (predicted_position, world.get::<Position>(*source))
+        {
+            correction_distance =
+                correction_distance.max(predicted_position.prediction_error(server_position));
+        }
+    }
+    if is_mispredicted {
+        diagnostics.mispredicted_ticks += 1;
+        diagnostics.record_correction(correction_distance);
+    }
+    is_mispredicted
+}
+
+// Checks predictions against the latest server tick, once it arrives. Only if something was
+// mispredicted, or other players' inputs arrived late, are the predictions reset to the server's
+// state and the inputs made since then replayed.
+pub fn rollback_predictions(world: &mut World) -> Vec<TickActions> {
+    let mut pending_check = world.resource_mut::<PendingCheck>();
+    let server_tick = pending_check.server_tick.take();
+    let has_late_inputs = std::mem::take(&mut pending_check.has_late_inputs);
+
+    let predictions = predicted_entities(world);
+    let mut diagnostics = world.resource::<PredictionDiagnostics>().clone();
+    let is_mispredicted = match server_tick {
+        Some(server_tick) => {
+            // inputs up to the server's tick never need replaying again, whether or not they are now
+            world.resource_mut::<TickHistory>().confirm(server_tick);
+            let predicted = world.resource_mut::<PredictionHistory>().take(server_tick);
+            check_predictions(world, predicted, &predictions, &mut diagnostics)
+        }
+        None => false,
+    };
+    let replays = if is_mispredicted || has_late_inputs {
+        world.resource::<TickHistory>().replays()
     } else {
         Vec::new()
+    };
+    if has_late_inputs && !is_mispredicted {
+        diagnostics.late_input_rollbacks += 1;
+    }
+    diagnostics.replayed_ticks += replays.len() as u32;
+    *world.resource_mut::<PredictionDiagnostics>() = diagnostics;
+    if !is_mispredicted && !has_late_inputs {
+        return Vec::new();
+    }
+
+    let previous_positions = predictions
+        .iter()
+        .filter_map(|(_, prediction)| {
+            let position = world.get::<Position>(*prediction)?;
+            Some((*prediction, Vec2::new(*position.x, *position.y)))
+        })
+        .collect();
+    world.resource_mut::<PendingCorrections>().0 = previous_positions;
+    let restorers = world
+        .resource::<RollbackComponents>()
+        .components
+        .iter()
+        .map(|component| component.restore)
+        .collect::<Vec<_>>();
+    for (source, prediction) in predictions {
+        for restore in restorers.iter() {
+            restore(world, source, prediction);
+        }
+    }
+    replays
+}
+
+// Keeps drawing predictions where they were before the latest rollback, once its inputs have been
+// replayed, so that the correction is eased in rather than snapped to
+pub fn smooth_corrections(
+    mut commands: Commands,
+    mut pending_corrections: ResMut<PendingCorrections>,
+    smoothing: Res<CorrectionSmoothing>,
+    mut position_query: Query<(&Position, Option<&mut CorrectionOffset>)>,
+) {
+    for (entity, previous_position) in pending_corrections.0.drain(..) {
+        let Ok((position, offset)) = position_query.get_mut(entity) else { continue };
+        let correction = Vec2::new(*position.x, *position.y) - previous_position;
+        if correction == Vec2::ZERO {
+            continue;
+        }
+        match offset {
+            Some(mut offset) => offset.add(correction, &smoothing),
+            None => {
+                let mut offset = CorrectionOffset::default();
+                offset.add(correction, &smoothing);
+                commands.entity(entity).insert(offset);
+            }
+        }
     }
 }
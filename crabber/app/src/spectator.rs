@@ -0,0 +1,138 @@
+use bevy::{
+    prelude::{
+        info, Camera2d, Commands, Entity, EventReader, Input, KeyCode, NextState, Query, Res,
+        ResMut, Resource, Time, Transform, With, Without,
+    },
+    window::{PrimaryWindow, Window},
+};
+
+use naia_bevy_client::events::MessageEvents;
+
+use crabber_graphics::CameraTarget;
+use crabber_protocol::{
+    channels::PlayerAssignmentChannel,
+    components::{Crab, Level},
+    constants::TILE_SIZE_F32,
+    messages::{LobbyAssignmentMessage, SpectatorAssignmentMessage},
+};
+
+use crate::{
+    components::{InterpolatedBy, SourceOf},
+    AppState,
+};
+
+// how fast the free camera pans, in pixels per second
+const FREE_CAMERA_SPEED: f32 = 400.;
+
+// The crabs that are drawn, which are the predicted or interpolated copies where crabs have them
+type DrawnCrab = (With<Crab>, Without<SourceOf>, Without<InterpolatedBy>);
+
+// The crab that a spectator's camera follows, if any
+#[derive(Resource, Default)]
+pub struct SpectatorCamera {
+    pub target: Option<Entity>,
+}
+
+pub fn receive_spectator_assignment_message(
+    mut event_reader: EventReader<MessageEvents>,
+    mut state: ResMut<NextState<AppState>>,
+) {
+    for event in event_reader.iter() {
+        for _ in event.read::<PlayerAssignmentChannel, SpectatorAssignmentMessage>() {
+            info!("Every room is full, spectating until a slot opens up");
+            state.set(AppState::Spectating);
+        }
+        for _ in event.read::<PlayerAssignmentChannel, LobbyAssignmentMessage>() {
+            info!("A slot opened up in the lobby");
+            state.set(AppState::Lobby);
+        }
+    }
+}
+
+// Tab follows the next crab, and Escape frees the camera
+pub fn cycle_camera_target(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut spectator_camera: ResMut<SpectatorCamera>,
+    // the predicted or interpolated copies of crabs are followed, rather than the crabs that they
+    // are drawn for
+    crab_query: Query<Entity, DrawnCrab>,
+) {
+    let next_target = if keys.just_pressed(KeyCode::Tab) {
+        let mut crabs = crab_query.iter().collect::<Vec<_>>();
+        crabs.sort();
+        let next_index = spectator_camera
+            .target
+            .and_then(|target| crabs.iter().position(|crab| *crab == target))
+            .map_or(0, |index| index + 1);
+        crabs.get(next_index).or(crabs.first()).copied()
+    } else if keys.just_pressed(KeyCode::Escape) {
+        None
+    } else {
+        return;
+    };
+
+    if let Some(previous_target) = spectator_camera.target {
+        if let Some(mut entity) = commands.get_entity(previous_target) {
+            entity.remove::<CameraTarget>();
+        }
+    }
+    if let Some(target) = next_target {
+        commands.entity(target).insert(CameraTarget);
+    }
+    spectator_camera.target = next_target;
+}
+
+// Without a crab to follow, the arrow keys pan the camera around the level.
+// Sideways panning stops at the edges of levels wider than the window.
+pub fn pan_free_camera(
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    spectator_camera: Res<SpectatorCamera>,
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
+    crab_query: Query<(), With<Crab>>,
+    level_query: Query<&Level>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) {
+    if spectator_camera
+        .target
+        .is_some_and(|target| crab_query.contains(target))
+    {
+        return;
+    }
+    let Ok(mut camera_transform) = camera_query.get_single_mut() else { return };
+    let mut direction_x = 0.;
+    let mut direction_y = 0.;
+    if keys.pressed(KeyCode::Up) {
+        direction_y += 1.;
+    }
+    if keys.pressed(KeyCode::Down) {
+        direction_y -= 1.;
+    }
+    if keys.pressed(KeyCode::Right) {
+        direction_x += 1.;
+    }
+    if keys.pressed(KeyCode::Left) {
+        direction_x -= 1.;
+    }
+    let distance = FREE_CAMERA_SPEED * time.delta_seconds();
+    camera_transform.translation.y += direction_y * distance;
+
+    let Ok(level) = level_query.get_single() else { return };
+    let half_window_width = window_query
+        .get_single()
+        .map(|window| window.width() / 2.)
+        .unwrap_or_default();
+    let half_level_width = *level.width as f32 * TILE_SIZE_F32 / 2.;
+    let max_x = (half_level_width - half_window_width).max(0.);
+    camera_transform.translation.x =
+        (camera_transform.translation.x + direction_x * distance).clamp(-max_x, max_x);
+}
+
+pub fn leave_spectating(mut commands: Commands, mut spectator_camera: ResMut<SpectatorCamera>) {
+    if let Some(target) = spectator_camera.target.take() {
+        if let Some(mut entity) = commands.get_entity(target) {
+            entity.remove::<CameraTarget>();
+        }
+    }
+}
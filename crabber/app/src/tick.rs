@@ -1,11 +1,17 @@
 use bevy::prelude::{EventReader, Query, ResMut};
 
-use naia_bevy_client::{events::ClientTickEvent, Client};
+use naia_bevy_client::{
+    events::{ClientTickEvent, MessageEvents},
+    Client,
+};
 
 use crabber_core::{EntityActionMap, TickActions};
-use crabber_protocol::{channels::PlayerInputChannel, messages::InputMessage};
+use crabber_protocol::{
+    channels::{PlayerInputChannel, RelayedInputChannel},
+    messages::{InputMessage, RelayedInputMessage},
+};
 
-use crate::{components::SourceOf, resources::TickHistory};
+use crate::{components::SourceOf, resources::TickHistory, rollback::PendingCheck};
 
 pub fn send_and_prepare_inputs(
     mut client: Client,
@@ -27,17 +33,41 @@ pub fn send_and_prepare_inputs(
                 client_tick,
                 &input_message,
             );
+            // and to the other players, who predict this crab too. The server knows which crab
+            // is the player's, so the message leaves it out.
+            client.send_message::<RelayedInputChannel, RelayedInputMessage>(
+                &RelayedInputMessage::new(*client_tick, action),
+            );
             if let Ok(SourceOf(prediction)) = sources_query.get(entity) {
                 predicted_actions.0.insert(*prediction, action);
             }
         }
 
-        tick_history
-            .0
-            .insert(*client_tick, predicted_actions.clone());
+        let predicted_actions = tick_history.insert(*client_tick, predicted_actions);
         // Also proxy actions to TickPlugin
         ticks.push((*client_tick, predicted_actions));
     }
 
     ticks
 }
+
+// Adds other players' inputs to the ticks that they are for, so that their crabs are predicted
+// along with the player's own. By the time an input arrives, its tick has usually been predicted
+// without it already, so it is replayed.
+pub fn receive_relayed_inputs(
+    client: Client,
+    mut event_reader: EventReader<MessageEvents>,
+    mut tick_history: ResMut<TickHistory>,
+    mut pending_check: ResMut<PendingCheck>,
+    sources_query: Query<&SourceOf>,
+) {
+    for event in event_reader.iter() {
+        for message in event.read::<RelayedInputChannel, RelayedInputMessage>() {
+            let Some(entity) = message.entity.get(&client) else { continue };
+            let Ok(SourceOf(prediction)) = sources_query.get(entity) else { continue };
+            if tick_history.insert_relayed(message.tick, *prediction, message.action) {
+                pending_check.has_late_inputs = true;
+            }
+        }
+    }
+}
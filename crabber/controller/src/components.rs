@@ -28,6 +28,12 @@ impl WASDControllerBundle {
     }
 }
 
+impl Default for WASDControllerBundle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Bundle)]
 pub struct ArrowKeysControllerBundle {
     input_manager: InputManagerBundle<Action>,
@@ -51,6 +57,12 @@ impl ArrowKeysControllerBundle {
     }
 }
 
+impl Default for ArrowKeysControllerBundle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Bundle)]
 pub struct GamepadControllerBundle {
     input_manager: InputManagerBundle<Action>,
@@ -0,0 +1,104 @@
+use bevy_app::{prelude::IntoSystemAppConfig, App};
+use bevy_ecs::{
+    prelude::{Changed, Local, OnEnter, Query, Res, ResMut},
+    schedule::SystemSet,
+    system::{Commands, Resource},
+};
+
+use common_e2e::Test;
+
+use crabber_controller::{components::Controller, ControllerPlugin};
+use crabber_graphics::{AssetsState, CameraTarget, GraphicsPlugin};
+use crabber_protocol::{
+    bundles::CrabBundle,
+    components::{Controlled, Level},
+    constants::{DEFAULT_LEVEL_HEIGHT, DEFAULT_LEVEL_WIDTH},
+    generation::LevelGenConfig,
+};
+
+use crabber_core::{EntityActionMap, TickActions, TickPlugin};
+
+fn read_actions(mut tick: Local<u16>, actions: Res<EntityActionMap>) -> Vec<TickActions> {
+    *tick = tick.wrapping_add(1);
+    vec![(*tick, actions.clone())]
+}
+
+// a fixed seed, so that every run climbs through the same rows
+const LEVEL_SEED: u64 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, SystemSet)]
+struct TickSet;
+
+// The first and end rows of the level when it was spawned, and after its latest change
+#[derive(Resource, Default)]
+struct LevelRows {
+    spawned: Option<(i16, i16)>,
+    latest: Option<(i16, i16)>,
+}
+
+// must spawn entities after entering new state to ensure that `Added` components are detected in graphics
+fn init(mut commands: Commands) {
+    // spawn an endless level, which the core loop extends as the crab climbs
+    let config = LevelGenConfig::from_env().expect("could not load the level generator config");
+    let level = Level::endless_from_seed_with_config(
+        LEVEL_SEED,
+        DEFAULT_LEVEL_WIDTH,
+        DEFAULT_LEVEL_HEIGHT,
+        config,
+    );
+    let (car_bundles, raft_bundles) = level.create_level_bundles();
+    for bundle in car_bundles.into_iter() {
+        commands.spawn((bundle, Controlled));
+    }
+    for bundle in raft_bundles.into_iter() {
+        commands.spawn((bundle, Controlled));
+    }
+
+    // spawn crab
+    commands.spawn((
+        CrabBundle::new(&level),
+        Controller::Keyboard(0),
+        Controlled,
+        CameraTarget,
+    ));
+
+    commands.spawn((level, Controlled));
+}
+
+fn track_level_rows(mut level_rows: ResMut<LevelRows>, level_query: Query<&Level, Changed<Level>>) {
+    for level in level_query.iter() {
+        let rows = (*level.first_row, level.end_row().0);
+        level_rows.spawned.get_or_insert(rows);
+        level_rows.latest = Some(rows);
+    }
+}
+
+// Passes once the level has been extended above where it started, and has dropped rows below
+fn level_moved_up(app: &App, _: ()) -> bool {
+    let level_rows = app.world.resource::<LevelRows>();
+    let (Some((first_row, end_row)), Some((latest_first_row, latest_end_row))) =
+        (level_rows.spawned, level_rows.latest)
+    else {
+        return false;
+    };
+    latest_end_row > end_row && latest_first_row > first_row
+}
+
+fn main() {
+    Test {
+        label: "Test endless level".to_string(),
+        setup: |app| {
+            app.add_plugin(TickPlugin::new(TickSet, read_actions))
+                .add_plugin(ControllerPlugin)
+                .init_resource::<LevelRows>()
+                .add_system(init.in_schedule(OnEnter(AssetsState::Ready)))
+                .add_system(track_level_rows);
+        },
+        setup_graphics: |app| {
+            app.add_plugin(GraphicsPlugin);
+        },
+        frames: 60,
+        check: level_moved_up,
+    }
+    .run();
+}
@@ -6,12 +6,17 @@ use common_e2e::Test;
 
 use crabber_controller::{components::Controller, ControllerPlugin};
 use crabber_graphics::{AssetsState, GraphicsPlugin as CrabGraphicsPlugin};
-use crabber_protocol::{bundles::CrabBundle, components::Controlled};
+use crabber_protocol::{
+    bundles::CrabBundle,
+    components::{Controlled, Level},
+};
 
 use crabber_core::{EntityActionMap, TickActions, TickPlugin};
 
 fn init(mut commands: Commands) {
-    commands.spawn((CrabBundle::new(), Controller::Keyboard(0), Controlled));
+    // the level is not spawned, so that the crab is free to move around
+    let level = Level::new_random().expect("the level should be solvable");
+    commands.spawn((CrabBundle::new(&level), Controller::Keyboard(0), Controlled));
 }
 
 fn read_actions(actions: Res<EntityActionMap>) -> Vec<TickActions> {
@@ -1,5 +1,5 @@
 use bevy_app::prelude::IntoSystemAppConfig;
-use bevy_ecs::prelude::{Commands, OnEnter, Res, SystemSet};
+use bevy_ecs::prelude::{Commands, Local, OnEnter, Res, SystemSet};
 
 use common_e2e::Test;
 
@@ -8,12 +8,14 @@ use crabber_graphics::{AssetsState, GraphicsPlugin};
 use crabber_protocol::{
     bundles::CrabBundle,
     components::{Controlled, Level},
+    generation::LevelGenConfig,
 };
 
 use crabber_core::{EntityActionMap, TickActions, TickPlugin};
 
-fn read_actions(actions: Res<EntityActionMap>) -> Vec<TickActions> {
-    vec![(0, actions.clone())]
+fn read_actions(mut tick: Local<u16>, actions: Res<EntityActionMap>) -> Vec<TickActions> {
+    *tick = tick.wrapping_add(1);
+    vec![(*tick, actions.clone())]
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, SystemSet)]
@@ -22,7 +24,8 @@ struct TickSet;
 // must spawn entities after entering new state to ensure that `Added` components are detected in graphics
 fn init(mut commands: Commands) {
     // spawn level
-    let level = Level::new_random();
+    let config = LevelGenConfig::from_env().expect("could not load the level generator config");
+    let level = Level::new_random_with_config(config).expect("the level should be solvable");
     let (car_bundles, raft_bundles) = level.create_level_bundles();
     for bundle in car_bundles.into_iter() {
         commands.spawn((bundle, Controlled));
@@ -30,11 +33,12 @@ fn init(mut commands: Commands) {
     for bundle in raft_bundles.into_iter() {
         commands.spawn((bundle, Controlled));
     }
-    commands.spawn(level);
 
     // spawn crab
-    commands.spawn((CrabBundle::new(), Controller::Keyboard(0), Controlled));
-    commands.spawn((CrabBundle::new(), Controller::Keyboard(1), Controlled));
+    commands.spawn((CrabBundle::new(&level), Controller::Keyboard(0), Controlled));
+    commands.spawn((CrabBundle::new(&level), Controller::Keyboard(1), Controlled));
+
+    commands.spawn(level);
 }
 
 fn main() {
@@ -1,6 +1,6 @@
 use bevy_app::IntoSystemAppConfig;
 use bevy_ecs::{
-    prelude::{Commands, OnEnter},
+    prelude::{Commands, Local, OnEnter},
     schedule::SystemSet,
 };
 
@@ -8,26 +8,31 @@ use common_e2e::Test;
 
 use crabber_core::{EntityActionMap, TickActions, TickPlugin};
 use crabber_protocol::{
-    components::{Car, ConstantMotor, Controlled, Direction, Position, Raft},
+    components::{Car, ConstantMotor, Controlled, Direction, Level, MotorOrigin, Position, Raft},
     constants::TILE_SIZE_F32,
 };
 
 use crabber_graphics::{AssetsState, GraphicsPlugin as CrabGraphicsPlugin};
 
-fn noop() -> Vec<TickActions> {
-    vec![(0, EntityActionMap::default())]
+fn noop(mut tick: Local<u16>) -> Vec<TickActions> {
+    *tick = tick.wrapping_add(1);
+    vec![(*tick, EntityActionMap::default())]
 }
 
 fn spawn_raft(mut commands: Commands) {
+    // motors loop around the bounds of the level
+    commands.spawn(Level::new_random().expect("the level should be solvable"));
     commands.spawn((
         Position::new(0., -TILE_SIZE_F32, Direction::Up),
         ConstantMotor::new(4., Direction::Right),
+        MotorOrigin::new(0., -TILE_SIZE_F32),
         Raft,
         Controlled,
     ));
     commands.spawn((
         Position::new(0., TILE_SIZE_F32, Direction::Up),
         ConstantMotor::new(4., Direction::Right),
+        MotorOrigin::new(0., TILE_SIZE_F32),
         Car,
         Controlled,
     ));
@@ -0,0 +1,122 @@
+use bevy_app::{prelude::IntoSystemAppConfig, App};
+use bevy_ecs::{
+    prelude::{Changed, Entity, Local, OnEnter, Query, Res, ResMut, With},
+    schedule::SystemSet,
+    system::{Commands, Resource},
+};
+use bevy_log::info;
+
+use common_e2e::Test;
+
+use crabber_controller::{components::Controller, ControllerPlugin};
+use crabber_graphics::{AssetsState, GraphicsPlugin};
+use crabber_protocol::{
+    bundles::CrabBundle,
+    components::{
+        Controlled, Crab, Knockout, Level, MatchPhase, MatchState, Winner, COUNTDOWN_TICKS,
+    },
+    generation::LevelGenConfig,
+};
+
+use crabber_core::{EntityActionMap, TickActions, TickPlugin};
+
+fn read_actions(mut tick: Local<u16>, actions: Res<EntityActionMap>) -> Vec<TickActions> {
+    *tick = tick.wrapping_add(1);
+    vec![(*tick, actions.clone())]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, SystemSet)]
+struct TickSet;
+
+// Each round and phase that the match went through, in order
+#[derive(Resource, Default)]
+struct PhaseLog(Vec<(u16, MatchPhase)>);
+
+// must spawn entities after entering new state to ensure that `Added` components are detected in graphics
+fn init(mut commands: Commands) {
+    // spawn a level with a round to play on it
+    let config = LevelGenConfig::from_env().expect("could not load the level generator config");
+    let level = Level::new_random_with_config(config).expect("the level should be solvable");
+    let (car_bundles, raft_bundles) = level.create_level_bundles();
+    for bundle in car_bundles.into_iter() {
+        commands.spawn((bundle, Controlled));
+    }
+    for bundle in raft_bundles.into_iter() {
+        commands.spawn((bundle, Controlled));
+    }
+
+    // spawn crab, which cannot move until the countdown ends
+    commands.spawn((CrabBundle::new(&level), Controller::Keyboard(0), Controlled));
+
+    commands.spawn((level, MatchState::new(1), Controlled));
+}
+
+fn log_match_phases(
+    mut phase_log: ResMut<PhaseLog>,
+    match_query: Query<&MatchState, Changed<MatchState>>,
+) {
+    for match_state in match_query.iter() {
+        let entry = (*match_state.round, *match_state.phase);
+        if phase_log.0.last() != Some(&entry) {
+            info!("Round {} entered {:?}", entry.0, entry.1);
+            phase_log.0.push(entry);
+        }
+    }
+}
+
+// Sends the crab back to the start once results have been shown, and counts down another round,
+// as the server does when it starts the next round
+fn start_next_round(
+    mut commands: Commands,
+    mut level_query: Query<(&Level, &mut MatchState)>,
+    crab_query: Query<Entity, With<Crab>>,
+) {
+    for (level, mut match_state) in level_query.iter_mut() {
+        if !match_state.is_finished() {
+            continue;
+        }
+        for entity in crab_query.iter() {
+            commands
+                .entity(entity)
+                .remove::<(Knockout, Winner)>()
+                .insert(CrabBundle::new(level));
+        }
+        *match_state.round = match_state.round.wrapping_add(1);
+        match_state.set_phase(MatchPhase::Countdown, COUNTDOWN_TICKS);
+    }
+}
+
+// Passes once a round has ended and a later round has been played
+fn reached_next_round(app: &App, _: ()) -> bool {
+    let PhaseLog(phases) = app.world.resource::<PhaseLog>();
+    let Some(round_over) = phases
+        .iter()
+        .position(|(_, phase)| *phase == MatchPhase::RoundOver)
+    else {
+        return false;
+    };
+    let (finished_round, _) = phases[round_over];
+    phases[round_over..]
+        .iter()
+        .any(|(round, _)| *round > finished_round)
+}
+
+fn main() {
+    Test {
+        label: "Test round lifecycle".to_string(),
+        setup: |app| {
+            app.add_plugin(TickPlugin::new(TickSet, read_actions))
+                .add_plugin(ControllerPlugin)
+                .init_resource::<PhaseLog>()
+                .add_system(init.in_schedule(OnEnter(AssetsState::Ready)))
+                .add_system(log_match_phases)
+                .add_system(start_next_round);
+        },
+        setup_graphics: |app| {
+            app.add_plugin(GraphicsPlugin);
+        },
+        frames: 60,
+        check: reached_next_round,
+    }
+    .run();
+}
@@ -1,26 +1,33 @@
 use bevy_ecs::{
     prelude::Entity,
-    query::{With, Without},
+    query::With,
     system::{Query, ResMut, Resource},
 };
 use bevy_utils::HashMap;
 
 use crabber_protocol::{
-    components::{Controlled, Knockout, Position, StepMotor},
+    components::{Level, MatchState, OnLevel, Position, StepMotor},
     inputs::InputAction,
 };
 
+use crate::{rounds::is_level_playing, tick::ControlledStepper};
+
 #[derive(Clone, Default, Debug, Resource)]
 pub struct EntityActionMap(pub HashMap<Entity, InputAction>);
 
 pub fn process_inputs(
     // Each player entity and the associated input action for this tick
     mut queued_inputs: ResMut<EntityActionMap>,
-    mut player_query: Query<(&mut Position, &mut StepMotor), (Without<Knockout>, With<Controlled>)>,
+    mut player_query: Query<(&mut Position, &mut StepMotor, Option<&OnLevel>), ControlledStepper>,
+    level_query: Query<Entity, With<Level>>,
+    match_query: Query<&MatchState>,
 ) {
+    let only_level = level_query.get_single().ok();
     for (entity, action) in queued_inputs.0.drain() {
-        if let Ok((mut position, mut motor)) = player_query.get_mut(entity) {
-            if !motor.is_running() {
+        if let Ok((mut position, mut motor, on_level)) = player_query.get_mut(entity) {
+            // crabs stay put until their round starts, and once it is over
+            let level_entity = OnLevel::resolve(on_level, only_level);
+            if !motor.is_running() && is_level_playing(level_entity, &match_query) {
                 motor.start(&mut position, action.get_direction());
             }
         }
@@ -3,20 +3,28 @@ use std::marker::PhantomData;
 use bevy_app::{App, Plugin};
 use bevy_ecs::{
     schedule::{
-        FreeSystemSet, IntoSystemConfig, IntoSystemConfigs, Schedule, ScheduleLabel, SystemSet,
+        FreeSystemSet, IntoSystemConfig, IntoSystemConfigs, Schedule, ScheduleLabel, Schedules,
+        SystemSet,
     },
-    system::{In, IntoPipeSystem, IntoSystem},
+    system::{In, IntoPipeSystem, IntoSystem, Resource},
     world::World,
 };
 
 mod inputs;
 pub use inputs::EntityActionMap;
 
+mod respawn;
+mod rounds;
 mod tick;
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, ScheduleLabel)]
 pub struct CoreTickSchedule;
 
+// Runs after each tick of the `CoreTickSchedule`, once its commands have been applied,
+// for plugins that need to see the state that every tick ends with
+#[derive(Debug, Hash, PartialEq, Eq, Clone, ScheduleLabel)]
+pub struct CorePostTickSchedule;
+
 fn build_core_tick_schedule() -> Schedule {
     let mut schedule = Schedule::new();
     schedule
@@ -35,15 +43,33 @@ fn build_core_tick_schedule() -> Schedule {
                 tick::tick_score,
             )
                 .after(tick::tick_step_motors),
+        )
+        .add_systems(
+            (tick::tick_endless_levels, tick::tick_obstacle_culling)
+                .chain()
+                .after(tick::tick_score),
+        )
+        .add_systems(
+            (respawn::tick_knockouts, rounds::tick_match_states)
+                .chain()
+                .after(tick::tick_river_collisions)
+                .after(tick::tick_road_collisions)
+                .after(tick::tick_score),
         );
     schedule
 }
 
+// The tick that the `CoreTickSchedule` is currently simulating
+#[derive(Clone, Copy, Default, Debug, Resource)]
+pub struct CurrentTick(pub u16);
+
 fn run_core_game_loop(In(ticks): In<Vec<(u16, EntityActionMap)>>, world: &mut World) {
-    for (_tick, tick_actions) in ticks {
+    for (tick, tick_actions) in ticks {
+        world.resource_mut::<CurrentTick>().0 = tick;
         let mut inputs = world.resource_mut::<EntityActionMap>();
         inputs.0 = tick_actions.0;
         world.run_schedule(CoreTickSchedule);
+        world.run_schedule(CorePostTickSchedule);
     }
 }
 
@@ -98,11 +124,20 @@ where
 {
     fn build(&self, app: &mut App) {
         app.init_resource::<EntityActionMap>()
-            .add_schedule(CoreTickSchedule, build_core_tick_schedule())
-            .add_system(
-                self.tick_system
-                    .pipe(run_core_game_loop)
-                    .in_set(self.tick_system_set),
-            );
+            .init_resource::<CurrentTick>()
+            .add_schedule(CoreTickSchedule, build_core_tick_schedule());
+        // apps with more than one tick plugin share this schedule, so it must not be replaced
+        if !app
+            .world
+            .resource::<Schedules>()
+            .contains(&CorePostTickSchedule)
+        {
+            app.add_schedule(CorePostTickSchedule, Schedule::new());
+        }
+        app.add_system(
+            self.tick_system
+                .pipe(run_core_game_loop)
+                .in_set(self.tick_system_set),
+        );
     }
 }
@@ -0,0 +1,58 @@
+use bevy_ecs::prelude::{Commands, Entity, Query};
+
+use crabber_protocol::{
+    bundles::CrabBundle,
+    components::{
+        Knockout, Level, Lives, OnLevel, Position, RespawnConfig, RespawnRule, Score, StepMotor,
+    },
+};
+
+use crate::tick::ControlledCrab;
+
+// Counts down each knocked out crab, returning it to the start of its level once it has waited
+// long enough, unless it is out of lives
+#[allow(clippy::type_complexity)]
+pub fn tick_knockouts(
+    mut commands: Commands,
+    level_query: Query<(Entity, &Level, Option<&RespawnRule>)>,
+    mut crab_query: Query<
+        (
+            Entity,
+            &mut Knockout,
+            &mut Position,
+            &mut StepMotor,
+            &mut Score,
+            &mut Lives,
+            Option<&OnLevel>,
+        ),
+        ControlledCrab,
+    >,
+) {
+    let only_level = level_query.get_single().ok().map(|(entity, _, _)| entity);
+    for (entity, mut knockout, mut position, mut motor, mut score, mut lives, on_level) in
+        crab_query.iter_mut()
+    {
+        let Some(level_entity) = OnLevel::resolve(on_level, only_level) else { continue };
+        let Ok((_, level, rule)) = level_query.get(level_entity) else { continue };
+        let config = rule.map_or(RespawnConfig::default(), |rule| *rule.config);
+
+        if *knockout.elapsed_ticks == 0 {
+            // the crab was just knocked out
+            *lives.lost += 1;
+            score.add_penalty(config.points_penalty);
+        }
+        *knockout.elapsed_ticks = knockout.elapsed_ticks.saturating_add(1);
+        if lives.is_out(&config) {
+            continue;
+        }
+
+        if *knockout.elapsed_ticks >= config.respawn_ticks {
+            let start_position = CrabBundle::start_position(level);
+            *position.x = *start_position.x;
+            *position.y = *start_position.y;
+            *position.direction = *start_position.direction;
+            motor.reset();
+            commands.entity(entity).remove::<Knockout>();
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use bevy_ecs::prelude::{Commands, Entity, Query, With};
+
+use crabber_protocol::components::{
+    Controlled, Knockout, Level, LevelRow, Lives, MatchPhase, MatchState, OnLevel, Position,
+    RespawnConfig, RespawnRule, Score, Winner, RESULTS_TICKS, ROUND_OVER_TICKS,
+};
+
+use crate::tick::ControlledCrab;
+
+// Whether crabs on a level may move, which is only while a round is being played
+pub(crate) fn is_level_playing(
+    level_entity: Option<Entity>,
+    match_query: &Query<&MatchState>,
+) -> bool {
+    level_entity
+        .and_then(|level_entity| match_query.get(level_entity).ok())
+        .is_none_or(|match_state| match_state.is_playing())
+}
+
+// The crab that wins a round where every crab is out of lives, which is the one with the best
+// score, as long as no other crab matches it
+fn find_best_scoring_crab(crabs: &[(Entity, f32, u16, bool)]) -> Option<Entity> {
+    let best_score = crabs.iter().map(|(_, _, score, _)| *score).max()?;
+    let mut best_crabs = crabs.iter().filter(|(_, _, score, _)| *score == best_score);
+    match (best_crabs.next(), best_crabs.next()) {
+        (Some((entity, _, _, _)), None) => Some(*entity),
+        _ => None,
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn tick_match_states(
+    mut commands: Commands,
+    mut level_query: Query<
+        (Entity, &Level, &mut MatchState, Option<&RespawnRule>),
+        With<Controlled>,
+    >,
+    crab_query: Query<
+        (
+            Entity,
+            &Position,
+            &Score,
+            Option<&Knockout>,
+            Option<&Lives>,
+            Option<&OnLevel>,
+        ),
+        ControlledCrab,
+    >,
+) {
+    let only_level = level_query
+        .get_single()
+        .ok()
+        .map(|(entity, _, _, _)| entity);
+    for (level_entity, level, mut match_state, rule) in level_query.iter_mut() {
+        let config = rule.map_or(RespawnConfig::default(), |rule| *rule.config);
+        if *match_state.phase_ticks > 0 {
+            *match_state.phase_ticks -= 1;
+            continue;
+        }
+        match *match_state.phase {
+            MatchPhase::Countdown => {
+                match_state.set_phase(MatchPhase::Playing, 0);
+            }
+            MatchPhase::Playing => {
+                let crabs = crab_query
+                    .iter()
+                    .filter(|(_, _, _, _, _, on_level)| {
+                        OnLevel::resolve(*on_level, only_level) == Some(level_entity)
+                    })
+                    .map(|(entity, position, score, knockout, lives, _)| {
+                        // knocked out crabs are only out of the round once they have no lives left
+                        let is_out =
+                            knockout.is_some() && lives.is_none_or(|lives| lives.is_out(&config));
+                        (entity, *position.y, *score.value, is_out)
+                    })
+                    .collect::<Vec<_>>();
+                if crabs.is_empty() {
+                    continue;
+                }
+
+                // the first crab over the finish line wins, or the furthest one if several cross it together
+                let finished_crab = crabs
+                    .iter()
+                    .filter(|(_, y, _, is_out)| {
+                        !is_out && level.is_row_of_kind(level.y_to_row(*y), LevelRow::Finish)
+                    })
+                    .max_by(|(_, y_a, _, _), (_, y_b, _, _)| y_a.total_cmp(y_b))
+                    .map(|(entity, _, _, _)| *entity);
+                let winner = if finished_crab.is_some() {
+                    finished_crab
+                } else if crabs.iter().all(|(_, _, _, is_out)| *is_out) {
+                    find_best_scoring_crab(&crabs)
+                } else {
+                    continue;
+                };
+
+                if let Some(winner) = winner {
+                    commands.entity(winner).insert(Winner);
+                }
+                match_state.set_phase(MatchPhase::RoundOver, ROUND_OVER_TICKS);
+            }
+            MatchPhase::RoundOver => {
+                match_state.set_phase(MatchPhase::Results, RESULTS_TICKS);
+            }
+            // results stay up until the next round replaces this level
+            MatchPhase::Results => {}
+        }
+    }
+}
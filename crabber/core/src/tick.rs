@@ -1,36 +1,86 @@
-use bevy_ecs::prelude::{Commands, Entity, Query, With, Without};
+use bevy_ecs::prelude::{Commands, Entity, Query, Res, With, Without};
 
 use crabber_protocol::{
     components::{
-        Car, ConstantMotor, Controlled, Crab, Knockout, Level, LevelRow, Position, Raft, Score,
-        StepMotor, TileRow,
+        Car, ConstantMotor, Controlled, Crab, Knockout, Level, LevelRow, MatchState, MotorOrigin,
+        OnLevel, Position, Raft, Score, StepMotor, TileRow,
     },
     constants::TILE_SIZE_F32,
 };
 
+use crate::{rounds::is_level_playing, CurrentTick};
+
+// endless levels keep this many rows generated above the highest crab
+const ENDLESS_ROWS_AHEAD: i16 = 20;
+// and drop rows (and their obstacles) this far below the lowest crab
+const ENDLESS_ROWS_BEHIND: i16 = 10;
+
+// filters for the entities that this side of the connection simulates
+pub(crate) type ControlledCrab = (With<Crab>, With<Controlled>);
+type StandingCrab = (With<Crab>, Without<Knockout>, With<Controlled>);
+type ControlledObstacle<T> = (With<T>, Without<Crab>, With<Controlled>);
+pub(crate) type ControlledStepper = (Without<Knockout>, With<Controlled>);
+
+// The level that entities without an `OnLevel` belong to, if there is only one
+fn get_only_level(level_query: &Query<(Entity, &Level)>) -> Option<Entity> {
+    level_query.get_single().ok().map(|(entity, _)| entity)
+}
+
 pub fn tick_constant_motors(
-    mut motor_query: Query<(&mut Position, &ConstantMotor), With<Controlled>>,
+    tick: Res<CurrentTick>,
+    level_query: Query<(Entity, &Level)>,
+    mut motor_query: Query<
+        (
+            &mut Position,
+            &ConstantMotor,
+            &MotorOrigin,
+            Option<&OnLevel>,
+        ),
+        With<Controlled>,
+    >,
 ) {
-    for (mut position, motor) in motor_query.iter_mut() {
-        motor.drive_and_loop(&mut position);
+    let only_level = get_only_level(&level_query);
+    for (mut position, motor, origin, on_level) in motor_query.iter_mut() {
+        let Some(level_entity) = OnLevel::resolve(on_level, only_level) else { continue };
+        let Ok((_, level)) = level_query.get(level_entity) else { continue };
+        motor.drive_to_tick(origin, level, tick.0, &mut position);
     }
 }
 
 pub fn tick_step_motors(
-    mut motor_query: Query<(&mut Position, &mut StepMotor), (Without<Knockout>, With<Controlled>)>,
+    mut motor_query: Query<(&mut Position, &mut StepMotor), ControlledStepper>,
 ) {
     for (mut position, mut motor) in motor_query.iter_mut() {
         motor.drive(&mut position);
     }
 }
 
+// award points for each new row that crabs reach, and bonuses for reaching the finish
+#[allow(clippy::type_complexity)]
 pub fn tick_score(
-    mut player_query: Query<(&mut Score, &Position), (Without<Knockout>, With<Controlled>)>,
+    level_query: Query<(Entity, &Level)>,
+    match_query: Query<&MatchState>,
+    mut player_query: Query<
+        (&mut Score, &Position, Option<&Knockout>, Option<&OnLevel>),
+        ControlledCrab,
+    >,
 ) {
-    for (mut score, position) in player_query.iter_mut() {
-        let current_tile_row = (*position.y / 64.) as u16;
-        if current_tile_row > *score.value {
-            *score.value = current_tile_row;
+    let only_level = get_only_level(&level_query);
+    for (mut score, position, knockout, on_level) in player_query.iter_mut() {
+        let Some(level_entity) = OnLevel::resolve(on_level, only_level) else { continue };
+        let Ok((_, level)) = level_query.get(level_entity) else { continue };
+        if score.has_finished() || !is_level_playing(Some(level_entity), &match_query) {
+            continue;
+        }
+        // the clock keeps running while a crab is knocked out
+        *score.elapsed_ticks = score.elapsed_ticks.saturating_add(1);
+        if knockout.is_some() {
+            continue;
+        }
+        let TileRow(row) = level.y_to_row(*position.y);
+        score.reach_row(TileRow(row));
+        if level.is_row_of_kind(TileRow(row), LevelRow::Finish) {
+            score.finish();
         }
     }
 }
@@ -43,25 +93,24 @@ fn do_tiles_collide(position_a: &Position, position_b: &Position) -> bool {
 
 pub fn tick_road_collisions(
     mut commands: Commands,
-    level_query: Query<&Level>,
-    player_query: Query<
-        (Entity, &Position, &StepMotor),
-        (With<Crab>, Without<Knockout>, With<Controlled>),
-    >,
-    car_query: Query<&Position, (With<Car>, Without<Crab>, With<Controlled>)>,
+    level_query: Query<(Entity, &Level)>,
+    player_query: Query<(Entity, &Position, &StepMotor, Option<&OnLevel>), StandingCrab>,
+    car_query: Query<(&Position, Option<&OnLevel>), ControlledObstacle<Car>>,
 ) {
-    if let Ok(level) = level_query.get_single() {
-        for (entity, position, motor) in player_query.iter() {
-            let row = TileRow::from(*position.y);
-            if !motor.is_running()
-                && level.is_row_of_kind(row, LevelRow::Road)
-                && car_query
-                    .iter()
-                    .any(|car_position| do_tiles_collide(position, car_position))
-            {
-                // knockout the player if any car collides with the player!
-                commands.entity(entity).insert(Knockout);
-            }
+    let only_level = get_only_level(&level_query);
+    for (entity, position, motor, on_level) in player_query.iter() {
+        let Some(level_entity) = OnLevel::resolve(on_level, only_level) else { continue };
+        let Ok((_, level)) = level_query.get(level_entity) else { continue };
+        let row = level.y_to_row(*position.y);
+        if !motor.is_running()
+            && level.is_row_of_kind(row, LevelRow::Road)
+            && car_query.iter().any(|(car_position, car_level)| {
+                OnLevel::resolve(car_level, only_level) == Some(level_entity)
+                    && do_tiles_collide(position, car_position)
+            })
+        {
+            // knockout the player if any car collides with the player!
+            commands.entity(entity).insert(Knockout::new());
         }
     }
 }
@@ -69,39 +118,92 @@ pub fn tick_road_collisions(
 // check whether the character is in the river, or carried by a raft
 pub fn tick_river_collisions(
     mut commands: Commands,
-    level_query: Query<&Level>,
-    mut player_query: Query<
-        (Entity, &mut Position, &StepMotor),
-        (With<Crab>, Without<Knockout>, With<Controlled>),
-    >,
-    raft_query: Query<(&Position, &ConstantMotor), (With<Raft>, Without<Crab>, With<Controlled>)>,
+    level_query: Query<(Entity, &Level)>,
+    mut player_query: Query<(Entity, &mut Position, &StepMotor, Option<&OnLevel>), StandingCrab>,
+    raft_query: Query<(&Position, &ConstantMotor, Option<&OnLevel>), ControlledObstacle<Raft>>,
 ) {
-    if let Ok(level) = level_query.get_single() {
-        for (entity, mut position, motor) in player_query.iter_mut() {
-            let row = TileRow::from(*position.y);
-            let mut should_crab_ko = false;
-
-            // if player is on a river
-            if !motor.is_running() && level.is_row_of_kind(row, LevelRow::River) {
-                if let Some(raft_motor) = raft_query.iter().find_map(|(raft_position, motor)| {
-                    if do_tiles_collide(&position, raft_position) {
-                        Some(motor)
-                    } else {
-                        None
-                    }
-                }) {
-                    // and also colliding on a raft, player will KO if they are driven offscreen
-                    should_crab_ko = raft_motor.drive_offscreen(&mut position);
-                } else {
-                    // and not on a raft, player is KO
-                    should_crab_ko = true;
-                }
+    let only_level = get_only_level(&level_query);
+    for (entity, mut position, motor, on_level) in player_query.iter_mut() {
+        let Some(level_entity) = OnLevel::resolve(on_level, only_level) else { continue };
+        let Ok((_, level)) = level_query.get(level_entity) else { continue };
+        let row = level.y_to_row(*position.y);
+        let mut should_crab_ko = false;
+
+        // if player is on a river
+        if !motor.is_running() && level.is_row_of_kind(row, LevelRow::River) {
+            let raft_motor = raft_query
+                .iter()
+                .find(|(raft_position, _, raft_level)| {
+                    OnLevel::resolve(*raft_level, only_level) == Some(level_entity)
+                        && do_tiles_collide(&position, raft_position)
+                })
+                .map(|(_, motor, _)| motor);
+            if let Some(raft_motor) = raft_motor {
+                // and also colliding on a raft, player will KO if they are driven offscreen
+                should_crab_ko = raft_motor.drive_offscreen(&mut position, level);
+            } else {
+                // and not on a raft, player is KO
+                should_crab_ko = true;
             }
+        }
 
-            if should_crab_ko {
-                // knockout the player!
-                commands.entity(entity).insert(Knockout);
+        if should_crab_ko {
+            // knockout the player!
+            commands.entity(entity).insert(Knockout::new());
+        }
+    }
+}
+
+pub fn tick_endless_levels(
+    mut commands: Commands,
+    mut level_query: Query<(Entity, &mut Level), With<Controlled>>,
+    player_query: Query<(&Position, Option<&OnLevel>), ControlledCrab>,
+) {
+    let only_level = level_query.get_single().ok().map(|(entity, _)| entity);
+    for (level_entity, mut level) in level_query.iter_mut() {
+        if !*level.endless {
+            continue;
+        }
+        let crab_rows = player_query
+            .iter()
+            .filter(|(_, on_level)| OnLevel::resolve(*on_level, only_level) == Some(level_entity))
+            .map(|(position, _)| level.y_to_row(*position.y).0)
+            .collect::<Vec<_>>();
+        let (Some(&lowest_row), Some(&highest_row)) = (crab_rows.iter().min(), crab_rows.iter().max()) else { continue };
+
+        let TileRow(previous_end_row) = level.end_row();
+        let end_row = highest_row + ENDLESS_ROWS_AHEAD;
+        if end_row > previous_end_row {
+            level.extend_rows(TileRow(end_row));
+            let (car_bundles, raft_bundles) =
+                level.create_rows_bundles(TileRow(previous_end_row), TileRow(end_row));
+            for bundle in car_bundles.into_iter() {
+                commands.spawn((bundle, OnLevel(level_entity), Controlled));
+            }
+            for bundle in raft_bundles.into_iter() {
+                commands.spawn((bundle, OnLevel(level_entity), Controlled));
             }
         }
+
+        let first_row = lowest_row - ENDLESS_ROWS_BEHIND;
+        if first_row > *level.first_row {
+            level.drop_rows_below(TileRow(first_row));
+        }
+    }
+}
+
+// despawn any obstacles in rows that the level no longer keeps around
+pub fn tick_obstacle_culling(
+    mut commands: Commands,
+    level_query: Query<(Entity, &Level)>,
+    obstacle_query: Query<(Entity, &MotorOrigin, Option<&OnLevel>), With<Controlled>>,
+) {
+    let only_level = get_only_level(&level_query);
+    for (entity, origin, on_level) in obstacle_query.iter() {
+        let Some(level_entity) = OnLevel::resolve(on_level, only_level) else { continue };
+        let Ok((_, level)) = level_query.get(level_entity) else { continue };
+        if level.y_to_row(origin.y).0 < *level.first_row {
+            commands.entity(entity).despawn();
+        }
     }
 }
@@ -2,15 +2,15 @@ use bevy::prelude::{Commands, IntoSystemAppConfig, OnEnter};
 
 use common_e2e::Test;
 
-use crabber_protocol::{bundles::CrabBundle, components::Controlled};
+use crabber_protocol::{bundles::CrabBundle, components::Level};
 
-use crabber_graphics::{AssetsState, GraphicsPlugin as CrabGraphicsPlugin};
+use crabber_graphics::{AssetsState, GraphicsPlugin as CrabGraphicsPlugin, OwnCrab};
 
 fn spawn_crab(mut commands: Commands) {
     commands.spawn((
-        CrabBundle::new(),
+        CrabBundle::new(&Level::new_random().expect("the level should be solvable")),
         // show without tint
-        Controlled,
+        OwnCrab,
     ));
 }
 
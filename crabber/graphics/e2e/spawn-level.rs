@@ -18,7 +18,7 @@ use crabber_protocol::{
 use crabber_graphics::{AssetsState, GraphicsPlugin as CrabGraphicsPlugin};
 
 fn spawn_level(mut commands: Commands) {
-    let level = Level::new_random();
+    let level = Level::new_random().expect("the level should be solvable");
     let (car_bundles, raft_bundles) = level.create_level_bundles();
     for bundle in car_bundles.into_iter() {
         commands.spawn((bundle, Controlled));
@@ -46,6 +46,7 @@ fn main() {
 }
 
 // a utility that attaches debug graphics to show where object transforms are centered
+#[allow(clippy::type_complexity)]
 pub fn handle_debug_graphic(
     mut commands: Commands,
     new_game_object_query: Query<
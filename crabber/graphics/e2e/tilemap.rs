@@ -1,4 +1,4 @@
-use bevy::prelude::{Commands, IntoSystemAppConfig, NextState, OnEnter, ResMut};
+use bevy::prelude::{Commands, IntoSystemAppConfig, OnEnter};
 
 use common_e2e::Test;
 
@@ -7,7 +7,7 @@ use crabber_protocol::components::Level;
 use crabber_graphics::{AssetsState, GraphicsPlugin as CrabGraphicsPlugin};
 
 fn spawn_level(mut commands: Commands) {
-    commands.spawn(Level::new_random());
+    commands.spawn(Level::new_random().expect("the level should be solvable"));
 }
 
 fn main() {
@@ -1,18 +1,25 @@
+use std::ops::Range;
+
 use bevy::{
     prelude::{
-        in_state, info, Added, App, Assets, BuildChildren, Camera2dBundle, Changed, Color,
-        Commands, Component, Entity, IntoSystemConfigs, IntoSystemSetConfig, Plugin, Quat, Query,
-        Res, SpatialBundle, States, SystemSet, Transform, With,
+        in_state, info, Added, App, Assets, BuildChildren, Camera2d, Camera2dBundle, Changed,
+        Color, Commands, Component, DespawnRecursiveExt, DetectChanges, Entity,
+        IntoSystemAppConfig, IntoSystemConfig, IntoSystemConfigs, IntoSystemSetConfig, OnEnter,
+        Plugin, Quat, Query, RemovedComponents, Res, Resource, SpatialBundle, States, SystemSet,
+        Transform, Visibility, Window, With, Without,
     },
     sprite::{SpriteSheetBundle, TextureAtlas, TextureAtlasSprite},
+    text::{Text, Text2dBundle, TextAlignment, TextStyle},
+    ui::{node_bundles::TextBundle, PositionType, Style, UiRect, Val},
+    window::PrimaryWindow,
 };
 
 use bevy_asset_loader::loading_state::{LoadingState, LoadingStateAppExt};
 
 use bevy_ecs_tilemap::{
     prelude::{
-        fill_tilemap_rect, get_tilemap_center_transform, TilemapGridSize, TilemapId, TilemapPlugin,
-        TilemapSize, TilemapTexture, TilemapTileSize, TilemapType,
+        fill_tilemap_rect, TilemapGridSize, TilemapId, TilemapPlugin, TilemapSize, TilemapTexture,
+        TilemapTileSize, TilemapType,
     },
     tiles::{TilePos, TileStorage, TileTextureIndex},
     TilemapBundle,
@@ -22,18 +29,33 @@ use rand::Rng;
 
 use crabber_protocol::{
     components::{
-        Car, Controlled, Crab, Direction, Knockout, Level, LevelRow, Position, Raft, StepMotor,
-    },
-    constants::{
-        BACKGROUND_Z, LEVEL_HEIGHT_U32, LEVEL_WIDTH_U32, LEVEL_Z, PLAYER_Z, TILE_SIZE_F32,
+        Car, Controlled, Crab, Direction, Knockout, Level, LevelRow, PlayerName, Position, Raft,
+        StepMotor, TileColumn, TileRow,
     },
+    constants::{BACKGROUND_Z, LABEL_Z, LEVEL_Z, PLAYER_Z, TILE_SIZE_F32},
 };
 
 mod resources;
-use resources::SpriteSheetAssets;
+use resources::{FontAssets, SpriteSheetAssets};
+mod smoothing;
+pub use smoothing::{CorrectionOffset, CorrectionSmoothing};
 
-#[derive(Component)]
-struct ShouldRender;
+// how far above a crab its name is shown
+const NAME_LABEL_OFFSET: f32 = TILE_SIZE_F32 * 0.6;
+const NAME_LABEL_FONT_SIZE: f32 = 16.;
+const DEBUG_OVERLAY_FONT_SIZE: f32 = 16.;
+const DEBUG_OVERLAY_MARGIN: f32 = 8.;
+const STATUS_PANEL_FONT_SIZE: f32 = 20.;
+const STATUS_PANEL_MARGIN: f32 = 16.;
+
+// server-controlled obstacles that were just replicated and still need a sprite
+type NewObstacle<T> = (Added<T>, With<Controlled>);
+type LevelTilemap<'a> = (
+    Entity,
+    &'a Level,
+    Option<&'a mut TileStorage>,
+    Option<&'a mut TilemapSize>,
+);
 
 fn direction_to_angle(direction: Direction) -> f32 {
     match direction {
@@ -54,9 +76,19 @@ fn position_to_transform(position: &Position, z: f32, rotated: bool) -> Transfor
     }
 }
 
-fn sync_transforms(mut position_query: Query<(&Position, &mut Transform, Option<&Crab>)>) {
-    for (position, mut transform, crab) in position_query.iter_mut() {
+fn sync_transforms(
+    mut position_query: Query<(
+        &Position,
+        &mut Transform,
+        Option<&Crab>,
+        Option<&CorrectionOffset>,
+    )>,
+) {
+    for (position, mut transform, crab, offset) in position_query.iter_mut() {
         *transform = position_to_transform(position, transform.translation.z, crab.is_some());
+        if let Some(CorrectionOffset(offset)) = offset {
+            transform.translation += offset.extend(0.);
+        }
     }
 }
 
@@ -67,17 +99,35 @@ fn handle_knockout(mut ko_query: Query<&mut TextureAtlasSprite, Added<Knockout>>
     }
 }
 
+// restores the sprites of crabs that return to the level
+fn handle_respawn(
+    mut respawned: RemovedComponents<Knockout>,
+    mut crab_query: Query<(&mut TextureAtlasSprite, Option<&OwnCrab>), With<Crab>>,
+) {
+    for entity in respawned.iter() {
+        let Ok((mut sprite, is_own)) = crab_query.get_mut(entity) else { continue };
+        sprite.color = crab_color(is_own.is_some());
+        sprite.flip_y = false;
+    }
+}
+
+fn crab_color(is_own: bool) -> Color {
+    if is_own {
+        Color::WHITE
+    } else {
+        // add a tint for non-player crabs
+        Color::rgba(1., 1., 1., 0.75)
+    }
+}
+
 fn setup_crab_sprites(
     mut commands: Commands,
-    added_crabs_query: Query<(Entity, &Position, Option<&Controlled>), Added<Crab>>,
+    added_crabs_query: Query<(Entity, &Position, Option<&OwnCrab>), Added<Crab>>,
     spritesheets: Res<SpriteSheetAssets>,
 ) {
-    for (entity, position, is_controlled) in added_crabs_query.iter() {
+    for (entity, position, is_own) in added_crabs_query.iter() {
         let mut sprite = TextureAtlasSprite::new(0);
-        if is_controlled.is_none() {
-            // add a tint for non-player crabs
-            sprite.color = Color::rgba(1., 1., 1., 0.75);
-        }
+        sprite.color = crab_color(is_own.is_some());
         commands.entity(entity).insert((SpriteSheetBundle {
             texture_atlas: spritesheets.crab.clone(),
             sprite,
@@ -87,6 +137,180 @@ fn setup_crab_sprites(
     }
 }
 
+// a crab can turn out to be the player's own after its sprite was set up
+fn tint_own_crab(
+    mut crab_query: Query<&mut TextureAtlasSprite, (Added<OwnCrab>, Without<Knockout>)>,
+) {
+    for mut sprite in crab_query.iter_mut() {
+        sprite.color = crab_color(true);
+    }
+}
+
+// Follows a crab to show the name of its player, without turning along with its sprite
+#[derive(Component)]
+struct NameLabel(Entity);
+
+fn name_label_transform(position: &Position) -> Transform {
+    Transform::from_xyz(*position.x, *position.y + NAME_LABEL_OFFSET, LABEL_Z)
+}
+
+fn setup_name_labels(
+    mut commands: Commands,
+    added_names_query: Query<(Entity, &PlayerName, &Position), Added<PlayerName>>,
+    fonts: Res<FontAssets>,
+) {
+    for (entity, player_name, position) in added_names_query.iter() {
+        let style = TextStyle {
+            font: fonts.label.clone(),
+            font_size: NAME_LABEL_FONT_SIZE,
+            color: Color::WHITE,
+        };
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section((*player_name.name).clone(), style)
+                    .with_alignment(TextAlignment::Center),
+                transform: name_label_transform(position),
+                ..Default::default()
+            },
+            NameLabel(entity),
+        ));
+    }
+}
+
+// Moves labels along with their crabs, hiding the labels of crabs that are not drawn
+fn sync_name_labels(
+    mut commands: Commands,
+    mut label_query: Query<(
+        Entity,
+        &NameLabel,
+        &mut Transform,
+        &mut Visibility,
+        &mut Text,
+    )>,
+    crab_query: Query<(
+        &PlayerName,
+        &Position,
+        Option<&TextureAtlasSprite>,
+        Option<&CorrectionOffset>,
+    )>,
+) {
+    for (label_entity, NameLabel(crab), mut transform, mut visibility, mut text) in
+        label_query.iter_mut()
+    {
+        let Ok((player_name, position, sprite, offset)) = crab_query.get(*crab) else {
+            commands.entity(label_entity).despawn_recursive();
+            continue;
+        };
+        *transform = name_label_transform(position);
+        if let Some(CorrectionOffset(offset)) = offset {
+            transform.translation += offset.extend(0.);
+        }
+        *visibility = if sprite.is_some() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if text.sections[0].value != *player_name.name {
+            text.sections[0].value = (*player_name.name).clone();
+        }
+    }
+}
+
+// Text shown in the corner of the screen to help with debugging, which other plugins fill in
+#[derive(Resource, Default)]
+pub struct DebugOverlay {
+    pub visible: bool,
+    pub text: String,
+}
+
+#[derive(Component)]
+struct DebugOverlayText;
+
+fn setup_debug_overlay(mut commands: Commands, fonts: Res<FontAssets>) {
+    let style = TextStyle {
+        font: fonts.label.clone(),
+        font_size: DEBUG_OVERLAY_FONT_SIZE,
+        color: Color::WHITE,
+    };
+    commands.spawn((
+        TextBundle::from_section("", style).with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(DEBUG_OVERLAY_MARGIN),
+                left: Val::Px(DEBUG_OVERLAY_MARGIN),
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+        DebugOverlayText,
+    ));
+}
+
+fn sync_debug_overlay(
+    overlay: Res<DebugOverlay>,
+    mut text_query: Query<(&mut Text, &mut Visibility), With<DebugOverlayText>>,
+) {
+    if !overlay.is_changed() {
+        return;
+    }
+    for (mut text, mut visibility) in text_query.iter_mut() {
+        text.sections[0].value = overlay.text.clone();
+        *visibility = if overlay.visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+// Text shown in the corner opposite the debug overlay, such as the lobby list,
+// which other plugins fill in
+#[derive(Resource, Default)]
+pub struct StatusPanel {
+    pub visible: bool,
+    pub text: String,
+}
+
+#[derive(Component)]
+struct StatusPanelText;
+
+fn setup_status_panel(mut commands: Commands, fonts: Res<FontAssets>) {
+    let style = TextStyle {
+        font: fonts.label.clone(),
+        font_size: STATUS_PANEL_FONT_SIZE,
+        color: Color::WHITE,
+    };
+    commands.spawn((
+        TextBundle::from_section("", style).with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(STATUS_PANEL_MARGIN),
+                right: Val::Px(STATUS_PANEL_MARGIN),
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+        StatusPanelText,
+    ));
+}
+
+fn sync_status_panel(
+    panel: Res<StatusPanel>,
+    mut text_query: Query<(&mut Text, &mut Visibility), With<StatusPanelText>>,
+) {
+    if !panel.is_changed() {
+        return;
+    }
+    for (mut text, mut visibility) in text_query.iter_mut() {
+        text.sections[0].value = panel.text.clone();
+        *visibility = if panel.visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
 fn get_car_sprite(direction: Direction) -> TextureAtlasSprite {
     let mut rng = rand::thread_rng();
     let random_color_offset = rng.gen_range(0..=2);
@@ -96,7 +320,7 @@ fn get_car_sprite(direction: Direction) -> TextureAtlasSprite {
 
 fn setup_car_sprites(
     mut commands: Commands,
-    added_cars_query: Query<(Entity, &Position), (Added<Car>, With<Controlled>)>,
+    added_cars_query: Query<(Entity, &Position), NewObstacle<Car>>,
     spritesheets: Res<SpriteSheetAssets>,
 ) {
     for (entity, position) in added_cars_query.iter() {
@@ -111,7 +335,7 @@ fn setup_car_sprites(
 
 fn setup_raft_sprites(
     mut commands: Commands,
-    added_rafts_query: Query<(Entity, &Position), (Added<Raft>, With<Controlled>)>,
+    added_rafts_query: Query<(Entity, &Position), NewObstacle<Raft>>,
     spritesheets: Res<SpriteSheetAssets>,
 ) {
     for (entity, position) in added_rafts_query.iter() {
@@ -134,70 +358,173 @@ fn setup_raft_sprites(
     }
 }
 
+fn row_texture_index(level_row: &LevelRow) -> TileTextureIndex {
+    TileTextureIndex(match level_row {
+        LevelRow::Grass => 1,
+        LevelRow::River => 0,
+        LevelRow::Road => 2,
+        LevelRow::Finish => 3,
+    })
+}
+
+// Spawns the tiles for the given rows of a level, which must already fit in the tile storage
+fn spawn_level_rows(
+    commands: &mut Commands,
+    map_entity: Entity,
+    level: &Level,
+    rows: Range<u32>,
+    tile_storage: &mut TileStorage,
+) {
+    let row_size = TilemapSize {
+        x: *level.width as u32,
+        y: 1,
+    };
+    for y in rows {
+        let Some(level_row) = level.get_row_kind(TileRow(y as i16)) else { continue };
+        fill_tilemap_rect(
+            row_texture_index(&level_row),
+            TilePos { x: 0, y },
+            row_size,
+            TilemapId(map_entity),
+            commands,
+            tile_storage,
+        );
+    }
+}
+
+// Builds the tilemap for a level, then extends it chunk by chunk as endless levels add rows
+// above and drop rows below. Tiles sit at their row in the level, so kept tiles never move.
 fn setup_level_tilemap(
     mut commands: Commands,
-    level_query: Query<(Entity, &Level), Added<Level>>,
+    mut level_query: Query<LevelTilemap, Changed<Level>>,
     spritesheets: Res<SpriteSheetAssets>,
     atlas_assets: Res<Assets<TextureAtlas>>,
 ) {
-    if let Ok((map_entity, level)) = level_query.get_single() {
-        info!("{:?}", map_entity);
+    for (map_entity, level, tile_storage, tilemap_size) in level_query.iter_mut() {
+        let first_row = (*level.first_row).max(0) as u32;
+        let TileRow(end_row) = level.end_row();
+        let end_row = (end_row.max(0) as u32).max(first_row);
 
-        let tilemap_size = TilemapSize {
-            x: LEVEL_WIDTH_U32,
-            y: LEVEL_HEIGHT_U32,
-        };
-        let row_size = TilemapSize {
-            x: LEVEL_WIDTH_U32,
-            y: 1,
-        };
-        let tile_size = TilemapTileSize {
-            x: TILE_SIZE_F32,
-            y: TILE_SIZE_F32,
-        };
+        let (Some(mut tile_storage), Some(mut tilemap_size)) = (tile_storage, tilemap_size) else {
+            info!("{:?}", map_entity);
+
+            let tilemap_size = TilemapSize {
+                x: *level.width as u32,
+                y: end_row,
+            };
+            let tile_size = TilemapTileSize {
+                x: TILE_SIZE_F32,
+                y: TILE_SIZE_F32,
+            };
+
+            let mut tile_storage = TileStorage::empty(tilemap_size);
+            spawn_level_rows(
+                &mut commands,
+                map_entity,
+                level,
+                first_row..end_row,
+                &mut tile_storage,
+            );
 
-        let mut tile_storage = TileStorage::empty(tilemap_size);
+            let grid_size: TilemapGridSize = tile_size.into();
+            let map_type = TilemapType::default();
+            let texture_atlas = atlas_assets.get(&spritesheets.level).unwrap();
 
-        for (y, level_row) in level.rows.iter().enumerate() {
-            let texture_index = TileTextureIndex(match level_row {
-                LevelRow::Grass => 1,
-                LevelRow::River => 0,
-                LevelRow::Road => 2,
-                LevelRow::Finish => 3,
+            commands.entity(map_entity).insert(TilemapBundle {
+                map_type,
+                tile_size,
+                grid_size,
+                size: tilemap_size,
+                storage: tile_storage,
+                texture: TilemapTexture::Single(texture_atlas.texture.clone()),
+                // the first tile is centered on the first column of the level's first row
+                transform: Transform::from_xyz(
+                    level.column_to_x(TileColumn(0)),
+                    level.row_to_y(TileRow(0)),
+                    BACKGROUND_Z,
+                ),
+                ..Default::default()
             });
+            continue;
+        };
 
-            fill_tilemap_rect(
-                texture_index,
-                TilePos { x: 0, y: y as u32 },
-                row_size,
-                TilemapId(map_entity),
+        // rows above the tilemap are spawned, keeping the tiles that are already there
+        let built_end_row = tile_storage.size.y;
+        if end_row > built_end_row {
+            let grown_size = TilemapSize {
+                x: tile_storage.size.x,
+                y: end_row,
+            };
+            // tiles are stored row by row, so existing tiles keep their place in a taller storage
+            let mut grown_storage = TileStorage::empty(grown_size);
+            for (grown_tile, tile) in grown_storage.iter_mut().zip(tile_storage.iter()) {
+                *grown_tile = *tile;
+            }
+            *tile_storage = grown_storage;
+            *tilemap_size = grown_size;
+            spawn_level_rows(
                 &mut commands,
+                map_entity,
+                level,
+                built_end_row.max(first_row)..end_row,
                 &mut tile_storage,
             );
         }
 
-        let grid_size: TilemapGridSize = tile_size.into();
-        let map_type = TilemapType::default();
-        let texture_atlas = atlas_assets.get(&spritesheets.level).unwrap();
-
-        commands.entity(map_entity).insert(TilemapBundle {
-            map_type,
-            tile_size,
-            grid_size,
-            size: tilemap_size,
-            storage: tile_storage,
-            texture: TilemapTexture::Single(texture_atlas.texture.clone()),
-            transform: get_tilemap_center_transform(
-                &tilemap_size,
-                &grid_size,
-                &map_type,
-                BACKGROUND_Z,
-            ),
-            ..Default::default()
-        });
+        // rows are only ever dropped from the bottom, so this stops at the last row dropped before
+        for y in (0..first_row.min(tile_storage.size.y)).rev() {
+            let mut dropped_any = false;
+            for x in 0..tile_storage.size.x {
+                let tile_pos = TilePos { x, y };
+                if let Some(tile_entity) = tile_storage.get(&tile_pos) {
+                    commands.entity(tile_entity).despawn();
+                    tile_storage.remove(&tile_pos);
+                    dropped_any = true;
+                }
+            }
+            if !dropped_any {
+                break;
+            }
+        }
     }
 }
 
+// Keeps the camera centered on its targets, without scrolling past the edges of the level
+fn follow_camera_targets(
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
+    target_query: Query<&Position, With<CameraTarget>>,
+    level_query: Query<&Level>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) {
+    let Ok(mut camera_transform) = camera_query.get_single_mut() else { return };
+    let Ok(level) = level_query.get_single() else { return };
+    let half_window_height = window_query
+        .get_single()
+        .map(|window| window.height() / 2.)
+        .unwrap_or_default();
+
+    let target_ys = target_query
+        .iter()
+        .map(|position| *position.y)
+        .collect::<Vec<_>>();
+    let target_y = if target_ys.is_empty() {
+        camera_transform.translation.y
+    } else {
+        target_ys.iter().sum::<f32>() / target_ys.len() as f32
+    };
+
+    let bottom = level.row_to_y(TileRow(*level.first_row)) - TILE_SIZE_F32 / 2.;
+    let top = level.row_to_y(level.end_row()) - TILE_SIZE_F32 / 2.;
+    camera_transform.translation.y = if *level.endless {
+        target_y.max(bottom + half_window_height)
+    } else if top - bottom <= half_window_height * 2. {
+        (top + bottom) / 2.
+    } else {
+        target_y.clamp(bottom + half_window_height, top - half_window_height)
+    };
+}
+
+#[allow(clippy::type_complexity)]
 fn animate_sprites(
     mut crab_query: Query<(&StepMotor, &mut TextureAtlasSprite), (Changed<StepMotor>, With<Crab>)>,
 ) {
@@ -206,6 +533,14 @@ fn animate_sprites(
     }
 }
 
+// Marks the entities that the camera should keep in view
+#[derive(Component)]
+pub struct CameraTarget;
+
+// Marks the crab of the player at this client, which is drawn without the tint of other crabs
+#[derive(Component)]
+pub struct OwnCrab;
+
 fn camera(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
 }
@@ -231,16 +566,30 @@ impl Plugin for GraphicsPlugin {
                 LoadingState::new(AssetsState::Loading).continue_to_state(AssetsState::Ready),
             )
             .add_collection_to_loading_state::<_, SpriteSheetAssets>(AssetsState::Loading)
+            .add_collection_to_loading_state::<_, FontAssets>(AssetsState::Loading)
+            .init_resource::<DebugOverlay>()
+            .init_resource::<StatusPanel>()
+            .init_resource::<CorrectionSmoothing>()
             .add_startup_system(camera)
+            .add_system(setup_debug_overlay.in_schedule(OnEnter(AssetsState::Ready)))
+            .add_system(setup_status_panel.in_schedule(OnEnter(AssetsState::Ready)))
             .add_systems(
                 (
                     handle_knockout,
+                    handle_respawn,
                     setup_crab_sprites,
+                    tint_own_crab,
                     setup_car_sprites,
                     setup_raft_sprites,
                     setup_level_tilemap,
                     animate_sprites,
+                    setup_name_labels,
+                    smoothing::decay_correction_offsets.before(sync_transforms),
                     sync_transforms,
+                    sync_name_labels.after(sync_transforms),
+                    follow_camera_targets.after(sync_transforms),
+                    sync_debug_overlay,
+                    sync_status_panel,
                 )
                     .in_set(GraphicsSet),
             );
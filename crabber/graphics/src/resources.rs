@@ -2,6 +2,7 @@ use bevy::{
   asset::{AssetServer, Assets},
   prelude::{Handle, Resource, Vec2},
   sprite::TextureAtlas,
+  text::Font,
 };
 
 use bevy_asset_loader::asset_collection::AssetCollection;
@@ -21,3 +22,9 @@ pub struct SpriteSheetAssets {
   #[asset(path = "spritesheets/raft.png")]
   pub raft: Handle<TextureAtlas>,
 }
+
+#[derive(Resource, AssetCollection)]
+pub struct FontAssets {
+  #[asset(path = "fonts/FiraSans-Bold.ttf")]
+  pub label: Handle<Font>,
+}
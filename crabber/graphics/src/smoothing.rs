@@ -0,0 +1,67 @@
+use bevy::{
+    prelude::{Component, Query, Res, Resource, Vec2},
+    time::Time,
+};
+
+use crabber_protocol::constants::TILE_SIZE_F32;
+
+// offsets smaller than this are too small to see, so they are dropped, in pixels
+const MIN_OFFSET: f32 = 0.1;
+
+// How corrections to predicted entities are eased in, rather than snapping sprites to them
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct CorrectionSmoothing {
+    pub enabled: bool,
+    // how long it takes for half of what is left of a correction to be shown, in seconds
+    pub half_life: f32,
+    // corrections further than this are shown at once, since easing a crab across the level
+    // (such as when it respawns) looks worse than a jump, in pixels
+    pub max_distance: f32,
+}
+
+impl Default for CorrectionSmoothing {
+    fn default() -> Self {
+        CorrectionSmoothing {
+            enabled: true,
+            half_life: 0.05,
+            max_distance: TILE_SIZE_F32 * 2.,
+        }
+    }
+}
+
+// How far an entity is drawn from its position, while a correction to it is eased in
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct CorrectionOffset(pub Vec2);
+
+impl CorrectionOffset {
+    // Keeps drawing the entity where it was before it was moved by `correction`
+    pub fn add(&mut self, correction: Vec2, smoothing: &CorrectionSmoothing) {
+        let offset = self.0 - correction;
+        self.0 = if smoothing.enabled && offset.length() <= smoothing.max_distance {
+            offset
+        } else {
+            Vec2::ZERO
+        };
+    }
+}
+
+pub(crate) fn decay_correction_offsets(
+    time: Res<Time>,
+    smoothing: Res<CorrectionSmoothing>,
+    mut offset_query: Query<&mut CorrectionOffset>,
+) {
+    let remaining = if smoothing.enabled && smoothing.half_life > 0. {
+        0.5_f32.powf(time.delta_seconds() / smoothing.half_life)
+    } else {
+        0.
+    };
+    for mut offset in offset_query.iter_mut() {
+        if offset.0 == Vec2::ZERO {
+            continue;
+        }
+        offset.0 *= remaining;
+        if offset.0.length() < MIN_OFFSET {
+            offset.0 = Vec2::ZERO;
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+};
+
+use crate::{
+    components::{ConstantMotor, Direction, Level, LevelRow, MotorOrigin, TileRow, MOTION_STEPS},
+    constants::TILE_SIZE_F32,
+    inputs::InputAction,
+};
+
+// How far ahead the search looks for a path, in ticks
+pub const SEARCH_HORIZON_TICKS: u16 = 1200;
+
+// An input that the crab should send at the given tick
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathStep {
+    pub tick: u16,
+    pub action: InputAction,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unsolvable {
+    // the level has no finish line to reach
+    NoFinishRow,
+    // no path reached the finish line within the search horizon
+    NoPath { highest_row: i16 },
+}
+
+impl fmt::Display for Unsolvable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unsolvable::NoFinishRow => write!(f, "level has no finish row"),
+            Unsolvable::NoPath { highest_row } => write!(
+                f,
+                "no path reaches the finish row within {} ticks (got as far as row {})",
+                SEARCH_HORIZON_TICKS, highest_row
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Unsolvable {}
+
+fn do_tiles_collide(a: (f32, f32), b: (f32, f32)) -> bool {
+    (a.0 - b.0).abs() < TILE_SIZE_F32 && (a.1 - b.1).abs() < TILE_SIZE_F32
+}
+
+// Replays the rules of the core tick systems for a single crab, without a `World`
+struct LevelSimulation<'a> {
+    level: &'a Level,
+    obstacles: HashMap<i16, Vec<(ConstantMotor, MotorOrigin)>>,
+}
+
+impl<'a> LevelSimulation<'a> {
+    fn new(level: &'a Level) -> Self {
+        let mut obstacles: HashMap<i16, Vec<(ConstantMotor, MotorOrigin)>> = HashMap::new();
+        let (car_bundles, raft_bundles) = level.create_level_bundles();
+        let motors = car_bundles
+            .into_iter()
+            .map(|(_, _, motor, origin)| (motor, origin))
+            .chain(
+                raft_bundles
+                    .into_iter()
+                    .map(|(_, _, motor, origin)| (motor, origin)),
+            );
+        for (motor, origin) in motors {
+            let TileRow(row) = level.y_to_row(origin.y);
+            obstacles.entry(row).or_default().push((motor, origin));
+        }
+        LevelSimulation { level, obstacles }
+    }
+
+    // Applies the collision rules to a crab standing still in the given row at the given tick,
+    // returning where the crab ends up, or `None` if it is knocked out
+    fn settle(&self, x: f32, row: i16, tick: u16) -> Option<f32> {
+        let y = self.level.row_to_y(TileRow(row));
+        let mut obstacle_positions = self
+            .obstacles
+            .get(&row)
+            .into_iter()
+            .flatten()
+            .map(|(motor, origin)| (motor, motor.position_at_tick(origin, self.level, tick)));
+        match self.level.get_row_kind(TileRow(row))? {
+            LevelRow::Road => {
+                if obstacle_positions.any(|(_, position)| do_tiles_collide((x, y), position)) {
+                    None
+                } else {
+                    Some(x)
+                }
+            }
+            LevelRow::River => {
+                let (motor, _) =
+                    obstacle_positions.find(|(_, position)| do_tiles_collide((x, y), *position))?;
+                let x = x + motor.direction.to_vec().x * *motor.speed;
+                let max_abs = self.level.max_abs_coordinate(*motor.direction);
+                let is_offscreen = match *motor.direction {
+                    Direction::Right => x > max_abs,
+                    Direction::Left => x < -max_abs,
+                    Direction::Up | Direction::Down => false,
+                };
+                if is_offscreen {
+                    None
+                } else {
+                    Some(x)
+                }
+            }
+            LevelRow::Grass | LevelRow::Finish => Some(x),
+        }
+    }
+}
+
+struct SearchNode {
+    x: f32,
+    row: i16,
+    // ticks since the start of the search
+    elapsed: u16,
+    parent: Option<usize>,
+    action: Option<InputAction>,
+}
+
+// Searches for a sequence of inputs that takes a crab from the first row to the finish row,
+// starting at the given tick. Obstacles move deterministically with the tick, so this
+// explores every (position, row, tick) state that the crab can reach by waiting or stepping.
+pub fn find_path(level: &Level, start_tick: u16) -> Result<Vec<PathStep>, Unsolvable> {
+    if !level.rows.contains(&LevelRow::Finish) {
+        return Err(Unsolvable::NoFinishRow);
+    }
+
+    let simulation = LevelSimulation::new(level);
+    let step_ticks = MOTION_STEPS as u16;
+    let max_abs_x = level.max_abs_coordinate(Direction::Right);
+
+    let mut nodes = vec![SearchNode {
+        x: 0.,
+        row: 0,
+        elapsed: 0,
+        parent: None,
+        action: None,
+    }];
+    let mut visited = HashSet::new();
+    visited.insert((0, 0, 0));
+    let mut queue = VecDeque::from([0]);
+    let mut highest_row = 0;
+
+    while let Some(index) = queue.pop_front() {
+        let (x, row, elapsed) = (nodes[index].x, nodes[index].row, nodes[index].elapsed);
+        if level.is_row_of_kind(TileRow(row), LevelRow::Finish) {
+            return Ok(collect_path(&nodes, index, start_tick));
+        }
+        highest_row = highest_row.max(row);
+
+        // either wait a tick, or take a step that lands after `step_ticks`
+        let moves = [
+            (None, 1),
+            (Some(InputAction::Up), step_ticks),
+            (Some(InputAction::Left), step_ticks),
+            (Some(InputAction::Right), step_ticks),
+            (Some(InputAction::Down), step_ticks),
+        ];
+        for (action, duration) in moves {
+            let Some(next_elapsed) = elapsed.checked_add(duration) else { continue };
+            if next_elapsed > SEARCH_HORIZON_TICKS {
+                continue;
+            }
+            let (next_x, next_row) = match action {
+                None => (x, row),
+                Some(InputAction::Up) => (x, row + 1),
+                Some(InputAction::Down) => (x, row - 1),
+                Some(InputAction::Left) => (x - TILE_SIZE_F32, row),
+                Some(InputAction::Right) => (x + TILE_SIZE_F32, row),
+            };
+            if next_x.abs() > max_abs_x {
+                continue;
+            }
+            let tick = start_tick.wrapping_add(next_elapsed);
+            let Some(next_x) = simulation.settle(next_x, next_row, tick) else { continue };
+            if !visited.insert((next_x.round() as i32, next_row, next_elapsed)) {
+                continue;
+            }
+            nodes.push(SearchNode {
+                x: next_x,
+                row: next_row,
+                elapsed: next_elapsed,
+                parent: Some(index),
+                action,
+            });
+            queue.push_back(nodes.len() - 1);
+        }
+    }
+
+    Err(Unsolvable::NoPath { highest_row })
+}
+
+fn collect_path(nodes: &[SearchNode], mut index: usize, start_tick: u16) -> Vec<PathStep> {
+    let mut path = Vec::new();
+    while let Some(parent) = nodes[index].parent {
+        if let Some(action) = nodes[index].action {
+            // inputs are processed on the tick after the crab comes to rest
+            let tick = start_tick.wrapping_add(nodes[parent].elapsed + 1);
+            path.push(PathStep { tick, action });
+        }
+        index = parent;
+    }
+    path.reverse();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level_file::LevelFile;
+
+    fn authored_level(source: &str) -> Level {
+        LevelFile::from_ron(source).unwrap().to_level().unwrap()
+    }
+
+    // Where the crab rests along a path, as (x, row, tick) for every tick that it is not moving,
+    // following the same timing as `collect_path`
+    fn resting_states(path: &[PathStep]) -> Vec<(f32, i16, u16)> {
+        let step_ticks = MOTION_STEPS as u16;
+        let (mut x, mut row, mut rest_start) = (0., 0, 0);
+        let mut states = Vec::new();
+        for step in path {
+            states.extend((rest_start..step.tick).map(|tick| (x, row, tick)));
+            match step.action {
+                InputAction::Up => row += 1,
+                InputAction::Down => row -= 1,
+                InputAction::Left => x -= TILE_SIZE_F32,
+                InputAction::Right => x += TILE_SIZE_F32,
+            }
+            rest_start = step.tick - 1 + step_ticks;
+        }
+        states.push((x, row, rest_start));
+        states
+    }
+
+    #[test]
+    fn river_without_rafts_is_unsolvable() {
+        let level = authored_level(
+            "(width: 10, rows: [(kind: Grass), (kind: River), (kind: Grass), (kind: Finish)])",
+        );
+        let error = find_path(&level, 0).unwrap_err();
+        assert_eq!(error, Unsolvable::NoPath { highest_row: 0 });
+        assert!(!error.to_string().is_empty());
+    }
+
+    #[test]
+    fn grass_level_is_crossed_to_the_finish() {
+        let level = authored_level(
+            "(width: 10, rows: [(kind: Grass), (kind: Grass), (kind: Grass), (kind: Finish)])",
+        );
+        let path = find_path(&level, 0).unwrap();
+        let (_, final_row, _) = *resting_states(&path).last().unwrap();
+        assert_eq!(
+            level.get_row_kind(TileRow(final_row)),
+            Some(LevelRow::Finish)
+        );
+    }
+
+    #[test]
+    fn road_path_never_shares_a_tile_with_a_car() {
+        let level = authored_level(
+            "(width: 10, rows: [
+                (kind: Grass),
+                (kind: Road, lane: Some((speed: 2.0, direction: Right, columns: [0, 5]))),
+                (kind: Road, lane: Some((speed: 1.5, direction: Left, columns: [2, 7]))),
+                (kind: Grass),
+                (kind: Finish),
+            ])",
+        );
+        let path = find_path(&level, 0).unwrap();
+        let states = resting_states(&path);
+        let (_, final_row, _) = *states.last().unwrap();
+        assert_eq!(
+            level.get_row_kind(TileRow(final_row)),
+            Some(LevelRow::Finish)
+        );
+
+        let (car_bundles, _) = level.create_level_bundles();
+        assert!(!car_bundles.is_empty());
+        for (x, row, tick) in states {
+            let y = level.row_to_y(TileRow(row));
+            for (_, _, motor, origin) in car_bundles.iter() {
+                let car = motor.position_at_tick(origin, &level, tick);
+                assert!(
+                    !do_tiles_collide((x, y), car),
+                    "the crab at ({}, {}) is hit by a car at tick {}",
+                    x,
+                    row,
+                    tick
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn solvable_levels_have_a_path() {
+        for seed in [0, 1, 0xc0ffee] {
+            let level = Level::solvable_from_seed(seed).unwrap();
+            assert!(find_path(&level, 0).is_ok(), "seed {} has no path", seed);
+        }
+    }
+}
@@ -1,6 +1,12 @@
 use bevy_ecs::prelude::Bundle;
 
-use crate::components::{Crab, Direction, Position, Score, StepMotor, TileRow};
+use crate::components::{
+    Car, ConstantMotor, Crab, Direction, Level, Lives, MotorOrigin, Position, Raft, Score,
+    StepMotor, TileRow,
+};
+
+pub type CarBundle = (Car, Position, ConstantMotor, MotorOrigin);
+pub type RaftBundle = (Raft, Position, ConstantMotor, MotorOrigin);
 
 #[derive(Bundle)]
 pub struct CrabBundle {
@@ -8,15 +14,22 @@ pub struct CrabBundle {
     motor: StepMotor,
     position: Position,
     score: Score,
+    lives: Lives,
 }
 
 impl CrabBundle {
-    pub fn new() -> Self {
+    // Where crabs start a level, and return to after a knockout
+    pub fn start_position(level: &Level) -> Position {
+        Position::new(0., level.row_to_y(TileRow(0)), Direction::Up)
+    }
+
+    pub fn new(level: &Level) -> Self {
         CrabBundle {
             crab: Crab,
             motor: StepMotor::new(),
-            position: Position::new(0., f32::from(TileRow(0)), Direction::Up),
+            position: CrabBundle::start_position(level),
             score: Score::new(),
+            lives: Lives::new(),
         }
     }
 }
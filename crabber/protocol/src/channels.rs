@@ -13,6 +13,21 @@ impl PlayerInputChannel {
     }
 }
 
+// Passes each player's inputs on to the other clients in their room as soon as the server has them,
+// rather than once their tick comes, so that other clients can predict every crab. Inputs that are
+// lost are made up for by the server's state, so it is unreliable.
+#[derive(Channel)]
+pub struct RelayedInputChannel;
+
+impl RelayedInputChannel {
+    pub fn add_to_protocol(protocol: &mut Protocol) {
+        protocol.add_channel::<RelayedInputChannel>(
+            ChannelDirection::Bidirectional,
+            ChannelMode::UnorderedUnreliable,
+        );
+    }
+}
+
 #[derive(Channel)]
 pub struct PlayerAssignmentChannel;
 
@@ -24,3 +39,39 @@ impl PlayerAssignmentChannel {
         );
     }
 }
+
+#[derive(Channel)]
+pub struct LevelChecksumChannel;
+
+impl LevelChecksumChannel {
+    pub fn add_to_protocol(protocol: &mut Protocol) {
+        protocol.add_channel::<LevelChecksumChannel>(
+            ChannelDirection::ServerToClient,
+            ChannelMode::UnorderedUnreliable,
+        );
+    }
+}
+
+#[derive(Channel)]
+pub struct LobbyChannel;
+
+impl LobbyChannel {
+    pub fn add_to_protocol(protocol: &mut Protocol) {
+        protocol.add_channel::<LobbyChannel>(
+            ChannelDirection::Bidirectional,
+            ChannelMode::OrderedReliable(ReliableSettings::default()),
+        );
+    }
+}
+
+#[derive(Channel)]
+pub struct LeaderboardChannel;
+
+impl LeaderboardChannel {
+    pub fn add_to_protocol(protocol: &mut Protocol) {
+        protocol.add_channel::<LeaderboardChannel>(
+            ChannelDirection::Bidirectional,
+            ChannelMode::UnorderedReliable(ReliableSettings::default()),
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use bevy_ecs::prelude::{Component, Resource};
+
+use naia_bevy_shared::{Property, Replicate, Serde};
+
+use crate::components::POINTS_PER_ROW;
+
+// Inserted on a crab when it is hit by a car or falls in the river
+#[derive(Component, Replicate)]
+pub struct Knockout {
+    // how long the crab has been knocked out
+    pub elapsed_ticks: Property<u16>,
+}
+
+impl Knockout {
+    pub fn new() -> Self {
+        Knockout::new_complete(0)
+    }
+}
+
+impl Default for Knockout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// How knocked out crabs return to the level
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serde, Resource)]
+pub struct RespawnConfig {
+    // how long a crab stays knocked out before returning to the start
+    pub respawn_ticks: u16,
+    // how many knockouts a crab can take before it is out of the round, if limited
+    pub lives: Option<u16>,
+    // the points a crab loses for each knockout
+    pub points_penalty: u16,
+}
+
+impl Default for RespawnConfig {
+    fn default() -> Self {
+        RespawnConfig {
+            // about 1.5 seconds
+            respawn_ticks: 90,
+            lives: Some(3),
+            // the points for two rows
+            points_penalty: 2 * POINTS_PER_ROW,
+        }
+    }
+}
+
+// The respawn rules of a level, which is attached to the level entity.
+// Crabs on levels without a `RespawnRule` use the default rules.
+#[derive(Component, Replicate)]
+pub struct RespawnRule {
+    pub config: Property<RespawnConfig>,
+}
+
+impl RespawnRule {
+    pub fn new(config: RespawnConfig) -> Self {
+        RespawnRule::new_complete(config)
+    }
+}
+
+// Counts the lives a crab has lost
+#[derive(Component, Replicate)]
+pub struct Lives {
+    pub lost: Property<u16>,
+}
+
+impl Lives {
+    pub fn new() -> Self {
+        Lives::new_complete(0)
+    }
+
+    // The lives left under the given rules, or `None` if lives are unlimited
+    pub fn remaining(&self, config: &RespawnConfig) -> Option<u16> {
+        config.lives.map(|lives| lives.saturating_sub(*self.lost))
+    }
+
+    // Whether the crab has lost all of its lives, and stays knocked out for the rest of the round
+    pub fn is_out(&self, config: &RespawnConfig) -> bool {
+        self.remaining(config) == Some(0)
+    }
+}
+
+impl Default for Lives {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,44 +1,29 @@
+// the generated `Level::new_complete` takes one argument per replicated field
+#![allow(clippy::too_many_arguments)]
+
+use std::fmt;
+
 use bevy_ecs::component::Component;
 use naia_bevy_shared::{Property, Replicate, Serde};
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 use crate::{
-    components::{Car, ConstantMotor, Direction, Position, Raft},
+    analysis::{find_path, Unsolvable},
+    bundles::{CarBundle, RaftBundle},
+    components::{Car, ConstantMotor, Direction, MotorOrigin, Position, Raft},
     constants::{
-        LEVEL_HEIGHT_F32, LEVEL_HEIGHT_I16, LEVEL_WIDTH_F32, LEVEL_WIDTH_I16, TILE_SIZE_F32,
-        TILE_SIZE_I16,
+        DEFAULT_LEVEL_HEIGHT, DEFAULT_LEVEL_WIDTH, LEVEL_GENERATOR_VERSION, TILE_SIZE_F32,
     },
+    generation::{LevelGenConfig, SpeedRange},
 };
 
 // Helpful conversion types for tile positions
+// (use the `Level` to convert between tiles and coordinates)
 pub struct TileColumn(pub i16);
 pub struct TileRow(pub i16);
 
-impl From<f32> for TileColumn {
-    fn from(x: f32) -> TileColumn {
-        TileColumn(((x - TILE_SIZE_F32 / 2.) / TILE_SIZE_F32 + LEVEL_WIDTH_F32 / 2.) as i16)
-    }
-}
-
-impl From<TileColumn> for f32 {
-    fn from(TileColumn(x): TileColumn) -> f32 {
-        ((x - LEVEL_WIDTH_I16 / 2) * TILE_SIZE_I16 + TILE_SIZE_I16 / 2) as f32
-    }
-}
-
-impl From<f32> for TileRow {
-    fn from(y: f32) -> TileRow {
-        TileRow(((y - TILE_SIZE_F32 / 2.) / TILE_SIZE_F32 + LEVEL_HEIGHT_F32 / 2.) as i16)
-    }
-}
-
-impl From<TileRow> for f32 {
-    fn from(TileRow(y): TileRow) -> f32 {
-        ((y - LEVEL_HEIGHT_I16 / 2) * TILE_SIZE_I16 + TILE_SIZE_I16 / 2) as f32
-    }
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Serde)]
+#[derive(Clone, Copy, Debug, PartialEq, Serde, serde::Deserialize)]
 pub enum LevelRow {
     Grass,
     River,
@@ -46,61 +31,79 @@ pub enum LevelRow {
     Finish,
 }
 
-impl LevelRow {
-    fn get_random_next(&self) -> LevelRow {
-        let mut rng = rand::thread_rng();
-        let r = rng.gen_range(0..=9);
-        match self {
-            LevelRow::Grass => match r {
-                0..=3 => LevelRow::Grass,
-                4..=6 => LevelRow::Road,
-                _ => LevelRow::River,
-            },
-            LevelRow::River => match r {
-                0..=4 => LevelRow::River,
-                5..=7 => LevelRow::Grass,
-                _ => LevelRow::Road,
-            },
-            LevelRow::Road => match r {
-                0..=5 => LevelRow::Road,
-                6..=8 => LevelRow::Grass,
-                _ => LevelRow::River,
-            },
-            LevelRow::Finish => LevelRow::Finish,
-        }
-    }
+// Every row pulls from its own pair of random streams (one to pick the row kind,
+// one to place its obstacles), so a single row can be rebuilt from the seed
+// without replaying any of the rows before it.
+#[derive(Clone, Copy)]
+enum RowStream {
+    Kind = 0,
+    Motors = 1,
 }
 
-fn select_random_left_or_right() -> Direction {
-    let mut rng = rand::thread_rng();
+fn seeded_row_rng(seed: u64, row_index: i16, stream: RowStream) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(row_index as u64 * 2 + stream as u64);
+    rng
+}
+
+// Picks the kind of the next row, given the rows that come before it
+fn get_seeded_next_row(
+    config: &LevelGenConfig,
+    seed: u64,
+    row_index: i16,
+    previous_rows: &[LevelRow],
+) -> LevelRow {
+    let mut rng = seeded_row_rng(seed, row_index, RowStream::Kind);
+    let previous_row = previous_rows.last().copied().unwrap_or(LevelRow::Grass);
+    let consecutive_hazards = previous_rows
+        .iter()
+        .rev()
+        .take_while(|row| matches!(row, LevelRow::Road | LevelRow::River))
+        .count() as u16;
+    config.get_random_next(&mut rng, previous_row, consecutive_hazards)
+}
+
+fn select_random_left_or_right(rng: &mut impl Rng) -> Direction {
     match rng.gen_range(0..=1) {
         0 => Direction::Left,
         _ => Direction::Right,
     }
 }
 
-fn build_random_motors(row_index: i16) -> Vec<(Position, ConstantMotor)> {
-    let mut rng = rand::thread_rng();
-    let speed = rng.gen_range(1.0..6.0);
-    let direction = select_random_left_or_right();
-    let y = f32::from(TileRow(row_index));
+fn build_random_motors(
+    rng: &mut impl Rng,
+    level: &Level,
+    row_index: i16,
+    speed_range: SpeedRange,
+) -> Vec<(Position, ConstantMotor, MotorOrigin)> {
+    let config = &*level.generator_config;
+    let speed = rng.gen_range(speed_range.min..speed_range.max);
+    let direction = select_random_left_or_right(rng);
+    let y = level.row_to_y(TileRow(row_index));
+    let width = *level.width as i16;
 
     let mut vec = Vec::new();
     let mut current_x: i16 = 0;
-    while current_x < LEVEL_WIDTH_I16 {
-        let position = rng.gen_range(current_x..LEVEL_WIDTH_I16);
+    while current_x < width {
+        let position = rng.gen_range(current_x..width);
 
         // spawn a random-size clump
-        let num_rafts = rng.gen_range(1i16..3);
+        let num_rafts = rng.gen_range(config.clump_size.min..=config.clump_size.max);
 
-        // space this clump from the next clump by at least 1
-        current_x = position + num_rafts + rng.gen_range(1i16..3);
+        // space this clump from the next clump
+        current_x = position + num_rafts + rng.gen_range(config.gap_size.min..=config.gap_size.max);
+
+        // sparser configs skip some clumps entirely
+        if config.density < 1. && !rng.gen_bool(config.density as f64) {
+            continue;
+        }
 
         for index in 0..num_rafts {
-            let x = f32::from(TileColumn(position + index));
+            let x = level.column_to_x(TileColumn(position + index));
             vec.push((
-                Position::new(x as f32, y as f32, direction),
-                ConstantMotor::new(speed, direction),
+                Position::new(x, y, direction),
+                ConstantMotor::new_looping(speed, direction, level),
+                MotorOrigin::new(x, y),
             ));
         }
     }
@@ -108,55 +111,525 @@ fn build_random_motors(row_index: i16) -> Vec<(Position, ConstantMotor)> {
     vec
 }
 
+// How many seeds to try before giving up on finding a solvable level
+const MAX_GENERATION_ATTEMPTS: usize = 16;
+
+// No seed in the chain generated a level that can be crossed.
+// The last level generated is kept, for callers that would rather play it than nothing.
+pub struct UnsolvableLevel {
+    pub level: Box<Level>,
+    pub reason: Unsolvable,
+}
+
+impl fmt::Debug for UnsolvableLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnsolvableLevel")
+            .field("seed", &*self.level.seed)
+            .field("reason", &self.reason)
+            .finish()
+    }
+}
+
+impl fmt::Display for UnsolvableLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "no solvable level after {} attempts: {}",
+            MAX_GENERATION_ATTEMPTS, self.reason
+        )
+    }
+}
+
+impl std::error::Error for UnsolvableLevel {}
+
+// The obstacles of a hand-authored row: every listed column starts with an obstacle,
+// and all of them drive in the same direction at the same speed
+#[derive(Clone, PartialEq, Serde)]
+pub struct Lane {
+    pub speed: f32,
+    pub direction: Direction,
+    pub columns: Vec<i16>,
+}
+
+fn build_lane_motors(
+    lane: &Lane,
+    level: &Level,
+    row_index: i16,
+) -> Vec<(Position, ConstantMotor, MotorOrigin)> {
+    let y = level.row_to_y(TileRow(row_index));
+    lane.columns
+        .iter()
+        .map(|column| {
+            let x = level.column_to_x(TileColumn(*column));
+            (
+                Position::new(x, y, lane.direction),
+                ConstantMotor::new_looping(lane.speed, lane.direction, level),
+                MotorOrigin::new(x, y),
+            )
+        })
+        .collect()
+}
+
+// Mixes the bits of a value, so that nearby inputs produce very different hashes
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// Summarizes where a set of obstacles will be at the given tick.
+// The result does not depend on the order in which obstacles are visited,
+// so peers can compare checksums without agreeing on entity ordering.
+pub fn obstacle_checksum<'a>(
+    obstacles: impl Iterator<Item = (&'a ConstantMotor, &'a MotorOrigin)>,
+    level: &Level,
+    tick: u16,
+) -> u64 {
+    obstacles.fold(0, |checksum: u64, (motor, origin)| {
+        let (x, y) = motor.position_at_tick(origin, level, tick);
+        let bits = ((x.to_bits() as u64) << 32) | y.to_bits() as u64;
+        checksum.wrapping_add(splitmix64(bits))
+    })
+}
+
 #[derive(Component, Replicate)]
 pub struct Level {
+    // the seed this level was generated from, which also drives its obstacle layout
+    pub seed: Property<u64>,
+    // the version of the generator that built this level from its seed
+    pub generator_version: Property<u16>,
+    // the level size, in tiles
+    // in endless levels, the height only describes the first screen of rows
+    pub width: Property<u16>,
+    pub height: Property<u16>,
+    // endless levels have no finish line, and keep generating rows as crabs climb
+    pub endless: Property<bool>,
+    // the index of the first row in `rows`, since endless levels drop rows far below the crabs
+    pub first_row: Property<i16>,
     pub rows: Property<Vec<LevelRow>>,
+    // hand-authored levels describe the obstacles of each row explicitly, in step with `rows`
+    // this is empty for generated levels, whose obstacles are built from the seed instead
+    pub lanes: Property<Vec<Option<Lane>>>,
+    // the tuning used to generate rows and obstacles from the seed
+    pub generator_config: Property<LevelGenConfig>,
 }
 
 impl Level {
-    pub fn new_random() -> Self {
-        let mut rows = Vec::new();
+    pub fn new_random() -> Result<Self, UnsolvableLevel> {
+        Level::new_random_with_config(LevelGenConfig::default())
+    }
+
+    pub fn new_random_with_config(config: LevelGenConfig) -> Result<Self, UnsolvableLevel> {
+        Level::solvable_from_seed_with_config(
+            rand::thread_rng().gen(),
+            DEFAULT_LEVEL_WIDTH,
+            DEFAULT_LEVEL_HEIGHT,
+            config,
+        )
+    }
+
+    pub fn solvable_from_seed(seed: u64) -> Result<Self, UnsolvableLevel> {
+        Level::solvable_from_seed_with_config(
+            seed,
+            DEFAULT_LEVEL_WIDTH,
+            DEFAULT_LEVEL_HEIGHT,
+            LevelGenConfig::default(),
+        )
+    }
+
+    // Generates levels from a chain of seeds, starting from the given seed,
+    // until one of them can be crossed.
+    // The level keeps the seed that generated it, so clients can still rebuild it.
+    pub fn solvable_from_seed_with_config(
+        seed: u64,
+        width: u16,
+        height: u16,
+        config: LevelGenConfig,
+    ) -> Result<Self, UnsolvableLevel> {
+        let mut seed = seed;
+        let mut attempts = 0;
+        loop {
+            let level = Level::from_seed_with_config(seed, width, height, config.clone());
+            let reason = match find_path(&level, 0) {
+                Ok(_) => return Ok(level),
+                Err(reason) => reason,
+            };
+            attempts += 1;
+            if attempts == MAX_GENERATION_ATTEMPTS {
+                return Err(UnsolvableLevel {
+                    level: Box::new(level),
+                    reason,
+                });
+            }
+            seed = splitmix64(seed);
+        }
+    }
+
+    pub fn from_seed(seed: u64) -> Self {
+        Level::from_seed_with_config(
+            seed,
+            DEFAULT_LEVEL_WIDTH,
+            DEFAULT_LEVEL_HEIGHT,
+            LevelGenConfig::default(),
+        )
+    }
+
+    pub fn from_seed_with_config(
+        seed: u64,
+        width: u16,
+        height: u16,
+        config: LevelGenConfig,
+    ) -> Self {
         // The level should start with grass
-        let mut level_row_kind = LevelRow::Grass;
-        rows.push(level_row_kind);
+        let mut rows = vec![LevelRow::Grass];
         // Then we should go up to the N-1 row from there
-        for _ in 1..(LEVEL_HEIGHT_I16 - 1) {
-            level_row_kind = level_row_kind.get_random_next();
+        for row_index in 1..(height as i16 - 1) {
+            let level_row_kind = get_seeded_next_row(&config, seed, row_index, &rows);
             rows.push(level_row_kind);
         }
         // Finally, add a finish line.
         rows.push(LevelRow::Finish);
-        Level::new_complete(rows)
+        Level::new_complete(
+            seed,
+            LEVEL_GENERATOR_VERSION,
+            width,
+            height,
+            false,
+            0,
+            rows,
+            Vec::new(),
+            config,
+        )
+    }
+
+    // Builds a level from hand-authored rows, where `lanes` holds the obstacles of each row
+    pub fn authored(width: u16, rows: Vec<LevelRow>, lanes: Vec<Option<Lane>>) -> Self {
+        let height = rows.len() as u16;
+        Level::new_complete(
+            0,
+            LEVEL_GENERATOR_VERSION,
+            width,
+            height,
+            false,
+            0,
+            rows,
+            lanes,
+            LevelGenConfig::default(),
+        )
+    }
+
+    pub fn new_random_endless() -> Self {
+        Level::new_random_endless_with_config(LevelGenConfig::default())
+    }
+
+    pub fn new_random_endless_with_config(config: LevelGenConfig) -> Self {
+        Level::endless_from_seed_with_config(
+            rand::thread_rng().gen(),
+            DEFAULT_LEVEL_WIDTH,
+            DEFAULT_LEVEL_HEIGHT,
+            config,
+        )
+    }
+
+    pub fn endless_from_seed(seed: u64) -> Self {
+        Level::endless_from_seed_with_config(
+            seed,
+            DEFAULT_LEVEL_WIDTH,
+            DEFAULT_LEVEL_HEIGHT,
+            LevelGenConfig::default(),
+        )
+    }
+
+    pub fn endless_from_seed_with_config(
+        seed: u64,
+        width: u16,
+        height: u16,
+        config: LevelGenConfig,
+    ) -> Self {
+        // Endless levels also start with grass, but there is never a finish line
+        let mut level = Level::new_complete(
+            seed,
+            LEVEL_GENERATOR_VERSION,
+            width,
+            height,
+            true,
+            0,
+            vec![LevelRow::Grass],
+            Vec::new(),
+            config,
+        );
+        level.extend_rows(TileRow(height as i16));
+        level
+    }
+
+    // The row after the last generated row
+    pub fn end_row(&self) -> TileRow {
+        TileRow(*self.first_row + self.rows.len() as i16)
+    }
+
+    pub fn get_row_kind(&self, TileRow(row): TileRow) -> Option<LevelRow> {
+        if row < *self.first_row {
+            return None;
+        }
+        self.rows.get((row - *self.first_row) as usize).copied()
+    }
+
+    fn get_lane(&self, TileRow(row): TileRow) -> Option<&Lane> {
+        if row < *self.first_row {
+            return None;
+        }
+        self.lanes
+            .get((row - *self.first_row) as usize)
+            .and_then(Option::as_ref)
+    }
+
+    // Generates rows for an endless level up to (but not including) the given row.
+    // Rows are generated from the seed, so they do not depend on when this is called.
+    pub fn extend_rows(&mut self, TileRow(end_row): TileRow) {
+        let TileRow(mut row_index) = self.end_row();
+        while row_index < end_row {
+            let next_row =
+                get_seeded_next_row(&self.generator_config, *self.seed, row_index, &self.rows);
+            self.rows.push(next_row);
+            row_index += 1;
+        }
+    }
+
+    // Forgets about rows below the given row, which can no longer be reached
+    pub fn drop_rows_below(&mut self, TileRow(row): TileRow) {
+        // the last row is always kept
+        let max_dropped_rows = (self.rows.len() as i16).saturating_sub(1);
+        if max_dropped_rows <= 0 {
+            return;
+        }
+        let dropped_rows = (row - *self.first_row).clamp(0, max_dropped_rows);
+        if dropped_rows > 0 {
+            self.rows.drain(..dropped_rows as usize);
+            if !self.lanes.is_empty() {
+                self.lanes.drain(..dropped_rows as usize);
+            }
+            *self.first_row += dropped_rows;
+        }
+    }
+
+    // The level is centered on the origin, so tile coordinates are
+    // offset by half of the level size in each dimension
+    pub fn x_to_column(&self, x: f32) -> TileColumn {
+        TileColumn(((x - TILE_SIZE_F32 / 2.) / TILE_SIZE_F32 + *self.width as f32 / 2.) as i16)
+    }
+
+    pub fn column_to_x(&self, TileColumn(x): TileColumn) -> f32 {
+        (x as f32 - *self.width as f32 / 2.) * TILE_SIZE_F32 + TILE_SIZE_F32 / 2.
+    }
+
+    pub fn y_to_row(&self, y: f32) -> TileRow {
+        TileRow(((y - TILE_SIZE_F32 / 2.) / TILE_SIZE_F32 + *self.height as f32 / 2.) as i16)
+    }
+
+    pub fn row_to_y(&self, TileRow(y): TileRow) -> f32 {
+        (y as f32 - *self.height as f32 / 2.) * TILE_SIZE_F32 + TILE_SIZE_F32 / 2.
+    }
+
+    // The furthest coordinate from the center that an object can reach
+    // before it is considered offscreen in the given direction
+    pub fn max_abs_coordinate(&self, direction: Direction) -> f32 {
+        let size = match direction {
+            Direction::Left | Direction::Right => *self.width,
+            Direction::Up | Direction::Down => *self.height,
+        };
+        (size as f32 / 2. - 1.) * TILE_SIZE_F32
+    }
+
+    // The distance an object travels in the given direction before it loops back around
+    pub fn loop_length(&self, direction: Direction) -> f32 {
+        let size = match direction {
+            Direction::Left | Direction::Right => *self.width,
+            Direction::Up | Direction::Down => *self.height,
+        };
+        size as f32 * TILE_SIZE_F32
+    }
+
+    pub fn is_offscreen(&self, position: &Position, direction: Direction) -> bool {
+        let max_abs = self.max_abs_coordinate(direction);
+        let current_value = match direction {
+            Direction::Left | Direction::Right => *position.x,
+            Direction::Up | Direction::Down => *position.y,
+        };
+        let motion_vector = match direction {
+            Direction::Left | Direction::Right => direction.to_vec().x,
+            Direction::Up | Direction::Down => direction.to_vec().y,
+        };
+        // we are past the max value in the expected dimension
+        current_value.abs() > max_abs
+            // and we are moving farther "outside" the bounds
+            && current_value.is_sign_positive() == motion_vector.is_sign_positive()
     }
 
-    pub fn create_level_bundles(
+    pub fn create_level_bundles(&self) -> (Vec<CarBundle>, Vec<RaftBundle>) {
+        self.create_rows_bundles(TileRow(*self.first_row), self.end_row())
+    }
+
+    // Builds the obstacles for every row from `start` up to (but not including) `end`
+    pub fn create_rows_bundles(
         &self,
-    ) -> (
-        Vec<(Car, Position, ConstantMotor)>,
-        Vec<(Raft, Position, ConstantMotor)>,
-    ) {
+        TileRow(start): TileRow,
+        TileRow(end): TileRow,
+    ) -> (Vec<CarBundle>, Vec<RaftBundle>) {
         let mut car_bundles = Vec::new();
         let mut raft_bundles = Vec::new();
-        // Then we should go up to the N-1 row from there
-        for (row_index, row_kind) in self.rows.iter().enumerate() {
-            if LevelRow::Road == *row_kind {
-                for (position, motor) in build_random_motors(row_index as i16).into_iter() {
-                    car_bundles.push((Car, position, motor));
+        for row_index in start..end {
+            let (cars, rafts) = self.create_row_bundles(TileRow(row_index));
+            car_bundles.extend(cars);
+            raft_bundles.extend(rafts);
+        }
+        (car_bundles, raft_bundles)
+    }
+
+    // Builds the obstacles for a single row, which are always the same for a given seed
+    pub fn create_row_bundles(
+        &self,
+        TileRow(row_index): TileRow,
+    ) -> (Vec<CarBundle>, Vec<RaftBundle>) {
+        let mut car_bundles = Vec::new();
+        let mut raft_bundles = Vec::new();
+        let row_kind = self.get_row_kind(TileRow(row_index));
+        let build_motors = || match self.get_lane(TileRow(row_index)) {
+            Some(lane) => build_lane_motors(lane, self, row_index),
+            None if self.lanes.is_empty() => {
+                match row_kind.and_then(|row| self.generator_config.speed_range(row)) {
+                    Some(speed_range) => {
+                        let mut rng = seeded_row_rng(*self.seed, row_index, RowStream::Motors);
+                        build_random_motors(&mut rng, self, row_index, speed_range)
+                    }
+                    None => Vec::new(),
+                }
+            }
+            // authored rows without a lane are left empty
+            None => Vec::new(),
+        };
+        match row_kind {
+            Some(LevelRow::Road) => {
+                for (position, motor, origin) in build_motors() {
+                    car_bundles.push((Car, position, motor, origin));
                 }
             }
-            if LevelRow::River == *row_kind {
-                for (position, motor) in build_random_motors(row_index as i16).into_iter() {
-                    raft_bundles.push((Raft, position, motor));
+            Some(LevelRow::River) => {
+                for (position, motor, origin) in build_motors() {
+                    raft_bundles.push((Raft, position, motor, origin));
                 }
             }
+            _ => {}
         }
         (car_bundles, raft_bundles)
     }
 
     pub fn is_row_of_kind(&self, row: TileRow, target: LevelRow) -> bool {
-        self.rows
-            .get(row.0 as usize)
-            .and_then(|row_kind| if *row_kind == target { Some(()) } else { None })
-            .is_some()
+        self.get_row_kind(row) == Some(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEEDS: [u64; 4] = [0, 1, 0xc0ffee, u64::MAX];
+
+    // What an obstacle starts as: its position, direction, speed and origin
+    fn describe_obstacles(
+        obstacles: &[(Position, ConstantMotor, MotorOrigin)],
+    ) -> Vec<(f32, f32, Direction, f32, f32, f32)> {
+        obstacles
+            .iter()
+            .map(|(position, motor, origin)| {
+                (
+                    *position.x,
+                    *position.y,
+                    *position.direction,
+                    *motor.speed,
+                    origin.x,
+                    origin.y,
+                )
+            })
+            .collect()
+    }
+
+    fn build_obstacles(level: &Level) -> Vec<(Position, ConstantMotor, MotorOrigin)> {
+        let (car_bundles, raft_bundles) = level.create_level_bundles();
+        car_bundles
+            .into_iter()
+            .map(|(_, position, motor, origin)| (position, motor, origin))
+            .chain(
+                raft_bundles
+                    .into_iter()
+                    .map(|(_, position, motor, origin)| (position, motor, origin)),
+            )
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_generates_same_level() {
+        for seed in SEEDS {
+            let level = Level::from_seed(seed);
+            let regenerated = Level::from_seed(seed);
+            assert!(
+                *level.rows == *regenerated.rows,
+                "rows differ for seed {}",
+                seed
+            );
+            assert!(
+                *level.lanes == *regenerated.lanes,
+                "lanes differ for seed {}",
+                seed
+            );
+
+            let obstacles = build_obstacles(&level);
+            assert!(!obstacles.is_empty(), "seed {} has no obstacles", seed);
+            assert!(
+                describe_obstacles(&obstacles)
+                    == describe_obstacles(&build_obstacles(&regenerated)),
+                "obstacles differ for seed {}",
+                seed
+            );
+        }
+    }
+
+    #[test]
+    fn obstacle_checksums_agree_for_independent_builds() {
+        for seed in SEEDS {
+            let level = Level::from_seed(seed);
+            let obstacles = build_obstacles(&level);
+            // built again from a level of its own, as a client would, and visited in reverse
+            let rebuilt = build_obstacles(&Level::from_seed(seed));
+            for tick in [0, 1, 37, 1000, u16::MAX] {
+                let checksum = obstacle_checksum(
+                    obstacles.iter().map(|(_, motor, origin)| (motor, origin)),
+                    &level,
+                    tick,
+                );
+                let rebuilt_checksum = obstacle_checksum(
+                    rebuilt
+                        .iter()
+                        .rev()
+                        .map(|(_, motor, origin)| (motor, origin)),
+                    &level,
+                    tick,
+                );
+                assert_eq!(checksum, rebuilt_checksum, "seed {} at tick {}", seed, tick);
+            }
+        }
+    }
+
+    #[test]
+    fn dropping_rows_keeps_the_last_row() {
+        let mut level = Level::endless_from_seed(7);
+        level.drop_rows_below(TileRow(i16::MAX));
+        assert_eq!(level.rows.len(), 1);
+        assert_eq!(level.end_row().0, DEFAULT_LEVEL_HEIGHT as i16);
+
+        let mut empty = Level::authored(DEFAULT_LEVEL_WIDTH, Vec::new(), Vec::new());
+        empty.drop_rows_below(TileRow(5));
+        assert!(empty.rows.is_empty());
+        assert_eq!(*empty.first_row, 0);
     }
 }
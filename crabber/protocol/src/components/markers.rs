@@ -9,5 +9,6 @@ pub struct Raft;
 #[derive(Component, Replicate)]
 pub struct Car;
 
+// Marks the crab that won the round on its level
 #[derive(Component, Replicate)]
-pub struct Knockout;
+pub struct Winner;
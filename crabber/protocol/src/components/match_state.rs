@@ -0,0 +1,52 @@
+use bevy_ecs::prelude::Component;
+
+use naia_bevy_shared::{Property, Replicate, Serde};
+
+// how long crabs wait at the start line before a round begins (about 3 seconds)
+pub const COUNTDOWN_TICKS: u16 = 180;
+// how long a finished round lingers before its results are shown (about 2 seconds)
+pub const ROUND_OVER_TICKS: u16 = 120;
+// how long results are shown before the next round starts (about 5 seconds)
+pub const RESULTS_TICKS: u16 = 300;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serde)]
+pub enum MatchPhase {
+    // crabs are waiting at the start line
+    Countdown,
+    // crabs are racing to the finish
+    Playing,
+    // a crab has finished, or every crab is knocked out
+    RoundOver,
+    // the winner is shown until the next round starts
+    Results,
+}
+
+// The round being played on a level, which is attached to the level entity.
+// Levels without a `MatchState` are always being played.
+#[derive(Component, Replicate)]
+pub struct MatchState {
+    pub phase: Property<MatchPhase>,
+    // the ticks left in the current phase, which runs until a round ends while `Playing`
+    pub phase_ticks: Property<u16>,
+    pub round: Property<u16>,
+}
+
+impl MatchState {
+    pub fn new(round: u16) -> Self {
+        MatchState::new_complete(MatchPhase::Countdown, COUNTDOWN_TICKS, round)
+    }
+
+    pub fn is_playing(&self) -> bool {
+        *self.phase == MatchPhase::Playing
+    }
+
+    // Whether results have been shown for long enough to start the next round
+    pub fn is_finished(&self) -> bool {
+        *self.phase == MatchPhase::Results && *self.phase_ticks == 0
+    }
+
+    pub fn set_phase(&mut self, phase: MatchPhase, phase_ticks: u16) {
+        *self.phase = phase;
+        *self.phase_ticks = phase_ticks;
+    }
+}
@@ -1,17 +1,30 @@
 mod level;
-pub use level::{Level, LevelRow, TileColumn, TileRow};
+pub use level::{obstacle_checksum, Lane, Level, LevelRow, TileColumn, TileRow, UnsolvableLevel};
 
 mod markers;
-pub use markers::{Car, Crab, Knockout, Raft};
+pub use markers::{Car, Crab, Raft, Winner};
+
+mod knockout;
+pub use knockout::{Knockout, Lives, RespawnConfig, RespawnRule};
+
+mod player_name;
+pub use player_name::{validate_player_name, PlayerName, PlayerNameError, MAX_PLAYER_NAME_LENGTH};
 
 mod position;
 pub use position::{Direction, Position};
 
 mod motors;
-pub use motors::{ConstantMotor, StepMotor};
+pub(crate) use motors::MOTION_STEPS;
+pub use motors::{ConstantMotor, MotorOrigin, StepMotor};
 
 mod score;
-pub use score::Score;
+pub use score::{Score, FINISH_BONUS, MAX_TIME_BONUS, POINTS_PER_ROW, TIME_BONUS_TICKS_PER_POINT};
 
 mod controlled;
 pub use controlled::Controlled;
+
+mod on_level;
+pub use on_level::OnLevel;
+
+mod match_state;
+pub use match_state::{MatchPhase, MatchState, COUNTDOWN_TICKS, RESULTS_TICKS, ROUND_OVER_TICKS};
@@ -1,38 +1,35 @@
 use bevy_ecs::prelude::Component;
-use bevy_math::prelude::Vec3;
 
 use naia_bevy_shared::{Property, Replicate};
 
-use crate::{
-    components::{Direction, Position},
-    constants::{LEVEL_HEIGHT_F32, LEVEL_WIDTH_F32, MAX_X_F32, MAX_Y_F32, TILE_SIZE_F32},
-};
-
-fn is_offscreen(position: &Position, direction: Direction) -> bool {
-    let max_abs = match direction {
-        Direction::Left | Direction::Right => MAX_X_F32,
-        Direction::Up | Direction::Down => MAX_Y_F32,
-    };
-    let current_value = match direction {
-        Direction::Left | Direction::Right => *position.x,
-        Direction::Up | Direction::Down => *position.y,
-    };
-    let motion_vector = match direction {
-        Direction::Left | Direction::Right => direction.to_vec().x,
-        Direction::Up | Direction::Down => direction.to_vec().y,
-    };
-    // we are past the max value in the expected dimension
-    current_value.abs() > max_abs
-        // and we are moving farther "outside" the bounds
-        && current_value.is_sign_positive() == motion_vector.is_sign_positive()
+use crate::components::{Direction, Level, Position};
+
+// Tick counters wrap around after this many ticks. Looping motors are tuned to
+// complete a whole number of loops in this period, so that their position can be
+// computed from any tick without a jump when the counter wraps.
+const TICK_PERIOD: u32 = 1 << 16;
+
+// wraps a coordinate so that it stays within one loop length "behind" the max value
+fn wrap_into_loop(value: f32, max_abs: f32, loop_length: f32, is_positive: bool) -> f32 {
+    if is_positive {
+        max_abs - (max_abs - value).rem_euclid(loop_length)
+    } else {
+        (value + max_abs).rem_euclid(loop_length) - max_abs
+    }
+}
+
+// The position of a `ConstantMotor` entity at tick 0, from which its position at
+// any other tick can be computed
+#[derive(Component, Clone, Copy, Debug)]
+pub struct MotorOrigin {
+    pub x: f32,
+    pub y: f32,
 }
 
-fn get_offset_for_loop(direction: Direction) -> Vec3 {
-    let amplitude_tiles = match direction {
-        Direction::Up | Direction::Down => LEVEL_HEIGHT_F32,
-        Direction::Left | Direction::Right => LEVEL_WIDTH_F32,
-    };
-    -direction.to_vec() * TILE_SIZE_F32 * amplitude_tiles
+impl MotorOrigin {
+    pub fn new(x: f32, y: f32) -> Self {
+        MotorOrigin { x, y }
+    }
 }
 
 #[derive(Component, Replicate)]
@@ -46,20 +43,64 @@ impl ConstantMotor {
         Self::new_complete(speed, direction)
     }
 
-    pub fn drive_offscreen(&self, position: &mut Position) -> bool {
+    // Rounds the speed so that the motor completes a whole number of loops every
+    // `TICK_PERIOD` ticks
+    pub fn new_looping(speed: f32, direction: Direction, level: &Level) -> Self {
+        let loop_length = level.loop_length(direction);
+        let loops = (speed * TICK_PERIOD as f32 / loop_length).round();
+        Self::new(loops * loop_length / TICK_PERIOD as f32, direction)
+    }
+
+    fn loops_per_period(&self, loop_length: f32) -> u32 {
+        (*self.speed * TICK_PERIOD as f32 / loop_length).round() as u32
+    }
+
+    pub fn drive_offscreen(&self, position: &mut Position, level: &Level) -> bool {
         let delta = self.direction.to_vec() * *self.speed;
         *position.x += delta.x;
         *position.y += delta.y;
-        is_offscreen(position, *self.direction)
+        level.is_offscreen(position, *self.direction)
     }
 
-    pub fn drive_and_loop(&self, position: &mut Position) {
-        if self.drive_offscreen(position) {
-            let delta = get_offset_for_loop(*self.direction);
-            *position.x += delta.x;
-            *position.y += delta.y;
+    // Computes where the motor will have driven (and looped) from its origin by the given tick.
+    // This uses integer math for the distance travelled, so every peer computes exactly
+    // the same coordinates.
+    pub fn position_at_tick(&self, origin: &MotorOrigin, level: &Level, tick: u16) -> (f32, f32) {
+        let loop_length = level.loop_length(*self.direction);
+        let max_abs = level.max_abs_coordinate(*self.direction);
+        let phase = self.loops_per_period(loop_length).wrapping_mul(tick as u32) % TICK_PERIOD;
+        let distance = phase as f32 * loop_length / TICK_PERIOD as f32;
+        match *self.direction {
+            Direction::Right => (
+                wrap_into_loop(origin.x + distance, max_abs, loop_length, true),
+                origin.y,
+            ),
+            Direction::Left => (
+                wrap_into_loop(origin.x - distance, max_abs, loop_length, false),
+                origin.y,
+            ),
+            Direction::Up => (
+                origin.x,
+                wrap_into_loop(origin.y + distance, max_abs, loop_length, true),
+            ),
+            Direction::Down => (
+                origin.x,
+                wrap_into_loop(origin.y - distance, max_abs, loop_length, false),
+            ),
         }
     }
+
+    pub fn drive_to_tick(
+        &self,
+        origin: &MotorOrigin,
+        level: &Level,
+        tick: u16,
+        position: &mut Position,
+    ) {
+        let (x, y) = self.position_at_tick(origin, level, tick);
+        *position.x = x;
+        *position.y = y;
+    }
 }
 
 #[derive(Component, Replicate)]
@@ -70,7 +111,7 @@ pub struct StepMotor {
 }
 
 const STEP_SPEED: f32 = 2.; // 4. pixels per tick
-const MOTION_STEPS: usize = 32; // 16 ticks per step * 4 px per tick = 64 px / step, which is 1 tile
+pub(crate) const MOTION_STEPS: usize = 32; // 16 ticks per step * 4 px per tick = 64 px / step, which is 1 tile
 
 impl StepMotor {
     pub fn new() -> Self {
@@ -107,3 +148,9 @@ impl StepMotor {
         *self.step = None;
     }
 }
+
+impl Default for StepMotor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
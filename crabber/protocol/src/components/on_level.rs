@@ -0,0 +1,13 @@
+use bevy_ecs::prelude::{Component, Entity};
+
+// Ties a crab or obstacle to the level it plays on, for worlds that hold several levels.
+// Entities without this component belong to the only level in the world.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OnLevel(pub Entity);
+
+impl OnLevel {
+    // Finds the level of an entity, given the level to fall back on when it is not tagged
+    pub fn resolve(on_level: Option<&OnLevel>, only_level: Option<Entity>) -> Option<Entity> {
+        on_level.map(|OnLevel(level)| *level).or(only_level)
+    }
+}
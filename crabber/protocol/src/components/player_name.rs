@@ -0,0 +1,64 @@
+use std::fmt;
+
+use bevy_ecs::prelude::Component;
+
+use naia_bevy_shared::{Property, Replicate};
+
+// the longest name a player can go by, in characters
+pub const MAX_PLAYER_NAME_LENGTH: usize = 16;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PlayerNameError {
+    Empty,
+    TooLong,
+    InvalidCharacter(char),
+}
+
+impl fmt::Display for PlayerNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerNameError::Empty => write!(f, "names cannot be empty"),
+            PlayerNameError::TooLong => write!(
+                f,
+                "names cannot be longer than {} characters",
+                MAX_PLAYER_NAME_LENGTH
+            ),
+            PlayerNameError::InvalidCharacter(character) => {
+                write!(f, "names cannot contain {:?}", character)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlayerNameError {}
+
+// Checks a name that a player asked to go by, returning it without surrounding whitespace.
+// Names are letters, digits, spaces, dashes and underscores, so they are easy to show anywhere.
+pub fn validate_player_name(name: &str) -> Result<String, PlayerNameError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(PlayerNameError::Empty);
+    }
+    if name.chars().count() > MAX_PLAYER_NAME_LENGTH {
+        return Err(PlayerNameError::TooLong);
+    }
+    if let Some(character) = name
+        .chars()
+        .find(|character| !(character.is_alphanumeric() || matches!(character, ' ' | '-' | '_')))
+    {
+        return Err(PlayerNameError::InvalidCharacter(character));
+    }
+    Ok(name.to_string())
+}
+
+// The name of the player controlling a crab
+#[derive(Component, Replicate)]
+pub struct PlayerName {
+    pub name: Property<String>,
+}
+
+impl PlayerName {
+    pub fn new(name: String) -> Self {
+        PlayerName::new_complete(name)
+    }
+}
@@ -3,7 +3,7 @@ use bevy_math::prelude::Vec3;
 
 use naia_bevy_shared::{Property, Replicate, Serde};
 
-#[derive(Clone, Copy, PartialEq, Serde)]
+#[derive(Clone, Copy, PartialEq, Serde, serde::Deserialize)]
 pub enum Direction {
     Up,
     Right,
@@ -2,13 +2,78 @@ use bevy_ecs::prelude::Component;
 
 use naia_bevy_shared::{Property, Replicate};
 
+use crate::components::TileRow;
+
+// points for each row a crab climbs past the start row
+pub const POINTS_PER_ROW: u16 = 10;
+// points for reaching the finish row
+pub const FINISH_BONUS: u16 = 100;
+// the most points a crab can earn by finishing quickly
+pub const MAX_TIME_BONUS: u16 = 100;
+// how many ticks it takes to lose a point of the time bonus (about half a second)
+pub const TIME_BONUS_TICKS_PER_POINT: u16 = 30;
+
+// A crab's score, along with the breakdown of how it was earned
 #[derive(Component, Replicate)]
 pub struct Score {
+    // the total, which is the sum of the points below, less the penalty
     pub value: Property<u16>,
+    // the furthest row the crab has reached
+    pub best_row: Property<i16>,
+    pub row_points: Property<u16>,
+    pub finish_bonus: Property<u16>,
+    pub time_bonus: Property<u16>,
+    // the points taken away by knockouts, which climbing back over the same rows does not recover
+    pub penalty: Property<u16>,
+    // how long the crab has been playing the round, until it finishes
+    pub elapsed_ticks: Property<u16>,
 }
 
 impl Score {
     pub fn new() -> Self {
-        Self::new_complete(0)
+        Self::new_complete(0, 0, 0, 0, 0, 0, 0)
+    }
+
+    pub fn has_finished(&self) -> bool {
+        *self.finish_bonus > 0
+    }
+
+    // Awards points for a row, if the crab has not reached it before
+    pub fn reach_row(&mut self, TileRow(row): TileRow) {
+        if row > *self.best_row {
+            *self.best_row = row;
+            *self.row_points = (row.max(0) as u16).saturating_mul(POINTS_PER_ROW);
+            self.update_value();
+        }
+    }
+
+    // Awards the finish bonus, and a time bonus that shrinks the longer the crab took
+    pub fn finish(&mut self) {
+        if self.has_finished() {
+            return;
+        }
+        *self.finish_bonus = FINISH_BONUS;
+        *self.time_bonus =
+            MAX_TIME_BONUS.saturating_sub(*self.elapsed_ticks / TIME_BONUS_TICKS_PER_POINT);
+        self.update_value();
+    }
+
+    pub fn add_penalty(&mut self, points: u16) {
+        *self.penalty = self.penalty.saturating_add(points);
+        self.update_value();
+    }
+
+    fn update_value(&mut self) {
+        let earned = self
+            .row_points
+            .saturating_add(*self.finish_bonus)
+            .saturating_add(*self.time_bonus);
+        *self.value = earned.saturating_sub(*self.penalty);
+    }
+}
+
+impl Default for Score {
+    fn default() -> Self {
+        Self::new()
     }
 }
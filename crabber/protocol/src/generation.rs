@@ -0,0 +1,300 @@
+use std::{fmt, path::Path};
+
+use naia_bevy_shared::Serde;
+use rand::Rng;
+
+use crate::components::LevelRow;
+
+// Local setups read a preset name or config path from this environment variable
+pub const DIFFICULTY_ENV_VAR: &str = "CRABBER_DIFFICULTY";
+
+// The chance of each row kind following a row, as (row kind, weight) pairs.
+// Weights are relative to the total weight of the list.
+pub type RowWeights = Vec<(LevelRow, u16)>;
+
+fn total_weight(weights: &RowWeights) -> u32 {
+    weights.iter().map(|(_, weight)| *weight as u32).sum()
+}
+
+// A range of obstacle speeds, in pixels per tick
+#[derive(Clone, Copy, PartialEq, Serde, serde::Deserialize)]
+pub struct SpeedRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+// An inclusive range of sizes, in tiles
+#[derive(Clone, Copy, PartialEq, Serde, serde::Deserialize)]
+pub struct SizeRange {
+    pub min: i16,
+    pub max: i16,
+}
+
+// Tunes how random levels are generated.
+// This is replicated with each level, since clients rebuild obstacles from it.
+//
+// Configs can be written in RON, e.g.:
+//
+// (
+//     grass_transitions: [(Grass, 4), (Road, 3), (River, 3)],
+//     river_transitions: [(River, 5), (Grass, 3), (Road, 2)],
+//     road_transitions: [(Road, 6), (Grass, 3), (River, 1)],
+//     road_speed: (min: 1.0, max: 6.0),
+//     river_speed: (min: 1.0, max: 6.0),
+//     density: 1.0,
+//     clump_size: (min: 1, max: 2),
+//     gap_size: (min: 1, max: 2),
+//     max_consecutive_hazards: None,
+// )
+#[derive(Clone, PartialEq, Serde, serde::Deserialize)]
+pub struct LevelGenConfig {
+    // the row transition matrix, with one list of weights for each row kind
+    pub grass_transitions: RowWeights,
+    pub river_transitions: RowWeights,
+    pub road_transitions: RowWeights,
+    pub road_speed: SpeedRange,
+    pub river_speed: SpeedRange,
+    // the chance that each clump of obstacles is placed in its lane
+    pub density: f32,
+    // how many obstacles are placed side by side
+    pub clump_size: SizeRange,
+    // the smallest space left after each clump of obstacles
+    pub gap_size: SizeRange,
+    // how many roads and rivers can follow each other before a grass row is forced
+    pub max_consecutive_hazards: Option<u16>,
+}
+
+impl Default for LevelGenConfig {
+    fn default() -> Self {
+        LevelGenConfig::normal()
+    }
+}
+
+#[derive(Debug)]
+pub enum LevelGenConfigError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    UnknownPreset(String),
+    Invalid(&'static str),
+}
+
+impl fmt::Display for LevelGenConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelGenConfigError::Io(error) => write!(f, "could not read config: {}", error),
+            LevelGenConfigError::Parse(error) => write!(f, "could not parse config: {}", error),
+            LevelGenConfigError::UnknownPreset(name) => {
+                write!(f, "{} is not a preset, or a path to a config file", name)
+            }
+            LevelGenConfigError::Invalid(reason) => write!(f, "invalid config: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for LevelGenConfigError {}
+
+impl LevelGenConfig {
+    pub fn easy() -> Self {
+        LevelGenConfig {
+            grass_transitions: vec![
+                (LevelRow::Grass, 5),
+                (LevelRow::Road, 3),
+                (LevelRow::River, 2),
+            ],
+            river_transitions: vec![
+                (LevelRow::River, 3),
+                (LevelRow::Grass, 6),
+                (LevelRow::Road, 1),
+            ],
+            road_transitions: vec![
+                (LevelRow::Road, 4),
+                (LevelRow::Grass, 5),
+                (LevelRow::River, 1),
+            ],
+            road_speed: SpeedRange { min: 1.0, max: 3.0 },
+            river_speed: SpeedRange { min: 1.0, max: 2.5 },
+            density: 0.75,
+            clump_size: SizeRange { min: 2, max: 3 },
+            gap_size: SizeRange { min: 2, max: 3 },
+            max_consecutive_hazards: Some(2),
+        }
+    }
+
+    // The original tuning of the generator
+    pub fn normal() -> Self {
+        LevelGenConfig {
+            grass_transitions: vec![
+                (LevelRow::Grass, 4),
+                (LevelRow::Road, 3),
+                (LevelRow::River, 3),
+            ],
+            river_transitions: vec![
+                (LevelRow::River, 5),
+                (LevelRow::Grass, 3),
+                (LevelRow::Road, 2),
+            ],
+            road_transitions: vec![
+                (LevelRow::Road, 6),
+                (LevelRow::Grass, 3),
+                (LevelRow::River, 1),
+            ],
+            road_speed: SpeedRange { min: 1.0, max: 6.0 },
+            river_speed: SpeedRange { min: 1.0, max: 6.0 },
+            density: 1.0,
+            clump_size: SizeRange { min: 1, max: 2 },
+            gap_size: SizeRange { min: 1, max: 2 },
+            max_consecutive_hazards: None,
+        }
+    }
+
+    pub fn hard() -> Self {
+        LevelGenConfig {
+            grass_transitions: vec![
+                (LevelRow::Grass, 2),
+                (LevelRow::Road, 4),
+                (LevelRow::River, 4),
+            ],
+            river_transitions: vec![
+                (LevelRow::River, 6),
+                (LevelRow::Grass, 2),
+                (LevelRow::Road, 2),
+            ],
+            road_transitions: vec![
+                (LevelRow::Road, 7),
+                (LevelRow::Grass, 1),
+                (LevelRow::River, 2),
+            ],
+            road_speed: SpeedRange { min: 3.0, max: 8.0 },
+            river_speed: SpeedRange { min: 2.0, max: 6.0 },
+            density: 1.0,
+            clump_size: SizeRange { min: 1, max: 2 },
+            gap_size: SizeRange { min: 1, max: 1 },
+            max_consecutive_hazards: None,
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "easy" => Some(LevelGenConfig::easy()),
+            "normal" => Some(LevelGenConfig::normal()),
+            "hard" => Some(LevelGenConfig::hard()),
+            _ => None,
+        }
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, LevelGenConfigError> {
+        let config: LevelGenConfig = ron::from_str(source).map_err(LevelGenConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LevelGenConfigError> {
+        let source = std::fs::read_to_string(path).map_err(LevelGenConfigError::Io)?;
+        LevelGenConfig::from_ron(&source)
+    }
+
+    // Finds a preset by name, or else loads a config file from the given path
+    pub fn from_preset_or_file(name_or_path: &str) -> Result<Self, LevelGenConfigError> {
+        if let Some(config) = LevelGenConfig::preset(name_or_path) {
+            return Ok(config);
+        }
+        if !Path::new(name_or_path).exists() {
+            return Err(LevelGenConfigError::UnknownPreset(name_or_path.to_string()));
+        }
+        LevelGenConfig::load(name_or_path)
+    }
+
+    // Reads the config named by `DIFFICULTY_ENV_VAR`, if it is set
+    pub fn from_env() -> Result<Self, LevelGenConfigError> {
+        match std::env::var(DIFFICULTY_ENV_VAR) {
+            Ok(name_or_path) => LevelGenConfig::from_preset_or_file(&name_or_path),
+            Err(_) => Ok(LevelGenConfig::default()),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), LevelGenConfigError> {
+        let transitions = [
+            &self.grass_transitions,
+            &self.river_transitions,
+            &self.road_transitions,
+        ];
+        if transitions.iter().any(|weights| total_weight(weights) == 0) {
+            return Err(LevelGenConfigError::Invalid(
+                "every row kind needs a transition with some weight",
+            ));
+        }
+        if transitions
+            .iter()
+            .any(|weights| weights.iter().any(|(row, _)| *row == LevelRow::Finish))
+        {
+            return Err(LevelGenConfigError::Invalid(
+                "finish rows are only placed at the end of a level",
+            ));
+        }
+        for speed in [self.road_speed, self.river_speed] {
+            if !(speed.min > 0. && speed.min < speed.max && speed.max.is_finite()) {
+                return Err(LevelGenConfigError::Invalid(
+                    "speed ranges must be positive, with min below max",
+                ));
+            }
+        }
+        if !(self.density > 0. && self.density <= 1.) {
+            return Err(LevelGenConfigError::Invalid(
+                "density must be above 0 and at most 1",
+            ));
+        }
+        for size in [self.clump_size, self.gap_size] {
+            if size.min < 1 || size.min > size.max {
+                return Err(LevelGenConfigError::Invalid(
+                    "size ranges must be at least 1, with min at most max",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn transitions_from(&self, row: LevelRow) -> &RowWeights {
+        match row {
+            LevelRow::Grass => &self.grass_transitions,
+            LevelRow::River => &self.river_transitions,
+            LevelRow::Road | LevelRow::Finish => &self.road_transitions,
+        }
+    }
+
+    // Picks the kind of the row after `previous_row`, which follows `consecutive_hazards`
+    // roads and rivers in a row
+    pub fn get_random_next(
+        &self,
+        rng: &mut impl Rng,
+        previous_row: LevelRow,
+        consecutive_hazards: u16,
+    ) -> LevelRow {
+        if previous_row == LevelRow::Finish {
+            return LevelRow::Finish;
+        }
+        let weights = self.transitions_from(previous_row);
+        let mut roll = rng.gen_range(0..total_weight(weights));
+        let mut next_row = previous_row;
+        for (row, weight) in weights.iter() {
+            if roll < *weight as u32 {
+                next_row = *row;
+                break;
+            }
+            roll -= *weight as u32;
+        }
+        match self.max_consecutive_hazards {
+            Some(max) if next_row != LevelRow::Grass && consecutive_hazards >= max => {
+                LevelRow::Grass
+            }
+            _ => next_row,
+        }
+    }
+
+    pub fn speed_range(&self, row: LevelRow) -> Option<SpeedRange> {
+        match row {
+            LevelRow::Road => Some(self.road_speed),
+            LevelRow::River => Some(self.river_speed),
+            LevelRow::Grass | LevelRow::Finish => None,
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::{fmt, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    bundles::{CarBundle, RaftBundle},
+    components::{Direction, Lane, Level, LevelRow},
+    constants::DEFAULT_LEVEL_WIDTH,
+};
+
+// A hand-authored level, as written in a RON file:
+//
+// (
+//     width: 10,
+//     rows: [
+//         (kind: Grass),
+//         (kind: Road, lane: Some((speed: 2.0, direction: Left, columns: [0, 1, 5]))),
+//         (kind: Finish),
+//     ],
+// )
+//
+// Rows are listed from the bottom of the level to the top.
+#[derive(Deserialize)]
+pub struct LevelFile {
+    #[serde(default = "default_width")]
+    pub width: u16,
+    pub rows: Vec<LevelFileRow>,
+}
+
+#[derive(Deserialize)]
+pub struct LevelFileRow {
+    pub kind: LevelRow,
+    #[serde(default)]
+    pub lane: Option<LevelFileLane>,
+}
+
+#[derive(Deserialize)]
+pub struct LevelFileLane {
+    pub speed: f32,
+    pub direction: Direction,
+    pub columns: Vec<i16>,
+}
+
+fn default_width() -> u16 {
+    DEFAULT_LEVEL_WIDTH
+}
+
+#[derive(Debug)]
+pub enum LevelFileError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    NoRows,
+    LaneOnStaticRow { row: usize },
+    InvalidLaneDirection { row: usize },
+    InvalidLaneSpeed { row: usize },
+    ColumnOutOfBounds { row: usize, column: i16 },
+}
+
+impl fmt::Display for LevelFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelFileError::Io(error) => write!(f, "could not read level file: {}", error),
+            LevelFileError::Parse(error) => write!(f, "could not parse level file: {}", error),
+            LevelFileError::NoRows => write!(f, "level has no rows"),
+            LevelFileError::LaneOnStaticRow { row } => {
+                write!(f, "row {} has a lane, but only roads and rivers can", row)
+            }
+            LevelFileError::InvalidLaneDirection { row } => {
+                write!(f, "the lane in row {} must move left or right", row)
+            }
+            LevelFileError::InvalidLaneSpeed { row } => {
+                write!(f, "the lane in row {} must have a positive speed", row)
+            }
+            LevelFileError::ColumnOutOfBounds { row, column } => {
+                write!(f, "column {} in row {} is outside the level", column, row)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LevelFileError {}
+
+impl LevelFile {
+    pub fn from_ron(source: &str) -> Result<Self, LevelFileError> {
+        ron::from_str(source).map_err(LevelFileError::Parse)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LevelFileError> {
+        let source = std::fs::read_to_string(path).map_err(LevelFileError::Io)?;
+        LevelFile::from_ron(&source)
+    }
+
+    // Checks that the file describes a playable level, and builds it
+    pub fn to_level(&self) -> Result<Level, LevelFileError> {
+        if self.rows.is_empty() {
+            return Err(LevelFileError::NoRows);
+        }
+        let mut rows = Vec::new();
+        let mut lanes = Vec::new();
+        for (row, file_row) in self.rows.iter().enumerate() {
+            let lane = match &file_row.lane {
+                Some(lane) => {
+                    if !matches!(file_row.kind, LevelRow::Road | LevelRow::River) {
+                        return Err(LevelFileError::LaneOnStaticRow { row });
+                    }
+                    if !matches!(lane.direction, Direction::Left | Direction::Right) {
+                        return Err(LevelFileError::InvalidLaneDirection { row });
+                    }
+                    if !lane.speed.is_finite() || lane.speed <= 0. {
+                        return Err(LevelFileError::InvalidLaneSpeed { row });
+                    }
+                    if let Some(column) = lane
+                        .columns
+                        .iter()
+                        .find(|column| !(0..self.width as i16).contains(*column))
+                    {
+                        return Err(LevelFileError::ColumnOutOfBounds {
+                            row,
+                            column: *column,
+                        });
+                    }
+                    Some(Lane {
+                        speed: lane.speed,
+                        direction: lane.direction,
+                        columns: lane.columns.clone(),
+                    })
+                }
+                None => None,
+            };
+            rows.push(file_row.kind);
+            lanes.push(lane);
+        }
+        Ok(Level::authored(self.width, rows, lanes))
+    }
+}
+
+// Loads a level file, along with the obstacles that should be spawned for it
+pub fn load_level(
+    path: impl AsRef<Path>,
+) -> Result<(Level, Vec<CarBundle>, Vec<RaftBundle>), LevelFileError> {
+    let level = LevelFile::load(path)?.to_level()?;
+    let (car_bundles, raft_bundles) = level.create_level_bundles();
+    Ok((level, car_bundles, raft_bundles))
+}
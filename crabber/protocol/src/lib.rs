@@ -1,40 +1,66 @@
 use std::time::Duration;
 
-use naia_bevy_shared::{LinkConditionerConfig, Protocol, ProtocolPlugin};
+use naia_bevy_shared::{Protocol, ProtocolPlugin};
 
+pub mod analysis;
 pub mod bundles;
 pub mod channels;
 pub mod components;
 pub mod constants;
+pub mod generation;
 pub mod inputs;
+pub mod level_file;
+pub mod link_condition;
 pub mod messages;
 
+// How often the server ticks, unless it is configured otherwise.
+// Clients follow the server's tick rate, but gameplay timings are tuned for this one.
+pub const DEFAULT_TICK_INTERVAL: Duration = Duration::from_millis(16);
+
 struct CrabberProtocolPlugin;
 
 impl ProtocolPlugin for CrabberProtocolPlugin {
     fn build(&self, protocol: &mut Protocol) {
         channels::PlayerInputChannel::add_to_protocol(protocol);
+        channels::RelayedInputChannel::add_to_protocol(protocol);
         channels::PlayerAssignmentChannel::add_to_protocol(protocol);
+        channels::LevelChecksumChannel::add_to_protocol(protocol);
+        channels::LobbyChannel::add_to_protocol(protocol);
+        channels::LeaderboardChannel::add_to_protocol(protocol);
 
         protocol
+            .add_message::<messages::AuthMessage>()
             .add_message::<messages::PlayerAssignmentMessage>()
+            .add_message::<messages::SpectatorAssignmentMessage>()
+            .add_message::<messages::LobbyAssignmentMessage>()
             .add_message::<messages::InputMessage>()
+            .add_message::<messages::RelayedInputMessage>()
+            .add_message::<messages::LevelChecksumMessage>()
+            .add_message::<messages::ReadyMessage>()
+            .add_message::<messages::LobbyStateMessage>()
+            .add_message::<messages::LeaderboardRequestMessage>()
+            .add_message::<messages::LeaderboardResponseMessage>()
             .add_component::<components::Crab>()
+            .add_component::<components::PlayerName>()
             .add_component::<components::Car>()
             .add_component::<components::Raft>()
             .add_component::<components::Position>()
             .add_component::<components::ConstantMotor>()
             .add_component::<components::StepMotor>()
             .add_component::<components::Knockout>()
+            .add_component::<components::Lives>()
+            .add_component::<components::RespawnRule>()
             .add_component::<components::Level>()
-            .add_component::<components::Score>();
+            .add_component::<components::Score>()
+            .add_component::<components::Winner>()
+            .add_component::<components::MatchState>();
     }
 }
 
+// The protocol without any simulated network conditions, which `LinkCondition::apply` adds
 pub fn protocol() -> Protocol {
     Protocol::builder()
-        .tick_interval(Duration::from_millis(16))
-        .link_condition(LinkConditionerConfig::good_condition())
+        .tick_interval(DEFAULT_TICK_INTERVAL)
         .add_plugin(CrabberProtocolPlugin)
         .build()
 }
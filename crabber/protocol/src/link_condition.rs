@@ -0,0 +1,152 @@
+use std::{fmt, str::FromStr};
+
+use bevy_ecs::prelude::Resource;
+use serde::Deserialize;
+
+use naia_bevy_shared::{LinkConditionerConfig, Protocol};
+
+// Local setups read the simulated network conditions from this environment variable
+pub const LINK_CONDITION_ENV_VAR: &str = "CRABBER_LINK_CONDITION";
+
+// The network conditions to simulate on packets as they arrive, which helps to reproduce
+// problems that only show up on bad links. Each side only delays what it receives, so a round trip
+// is delayed by the conditions on both the client and the server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Resource)]
+pub enum LinkCondition {
+    // packets arrive as the real network delivers them
+    Perfect,
+    #[default]
+    Good,
+    Average,
+    Poor,
+    Custom {
+        latency_ms: u32,
+        // the most that latency is randomly shortened or lengthened by
+        jitter_ms: u32,
+        // the chance of dropping each packet, from 0 to 1
+        loss: f32,
+    },
+}
+
+#[derive(Debug)]
+pub enum LinkConditionError {
+    UnknownPreset(String),
+    Invalid(&'static str),
+}
+
+impl fmt::Display for LinkConditionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkConditionError::UnknownPreset(name) => write!(
+                f,
+                "{} is not a preset, or latency, jitter and loss like 120,30,0.05",
+                name
+            ),
+            LinkConditionError::Invalid(reason) => write!(f, "invalid link condition: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for LinkConditionError {}
+
+impl LinkCondition {
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "perfect" => Some(LinkCondition::Perfect),
+            "good" => Some(LinkCondition::Good),
+            "average" => Some(LinkCondition::Average),
+            "poor" => Some(LinkCondition::Poor),
+            _ => None,
+        }
+    }
+
+    // Reads the condition named by `LINK_CONDITION_ENV_VAR`, if it is set
+    pub fn from_env() -> Result<Self, LinkConditionError> {
+        match std::env::var(LINK_CONDITION_ENV_VAR) {
+            Ok(name_or_values) => name_or_values.parse(),
+            Err(_) => Ok(LinkCondition::default()),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), LinkConditionError> {
+        if let LinkCondition::Custom {
+            latency_ms,
+            jitter_ms,
+            loss,
+        } = self
+        {
+            if jitter_ms > latency_ms {
+                return Err(LinkConditionError::Invalid(
+                    "jitter must not be more than latency",
+                ));
+            }
+            if !(0.0..=1.0).contains(loss) {
+                return Err(LinkConditionError::Invalid("loss must be from 0 to 1"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_config(self) -> Option<LinkConditionerConfig> {
+        match self {
+            LinkCondition::Perfect => None,
+            LinkCondition::Good => Some(LinkConditionerConfig::good_condition()),
+            LinkCondition::Average => Some(LinkConditionerConfig::average_condition()),
+            LinkCondition::Poor => Some(LinkConditionerConfig::poor_condition()),
+            LinkCondition::Custom {
+                latency_ms,
+                jitter_ms,
+                loss,
+            } => Some(LinkConditionerConfig::new(latency_ms, jitter_ms, loss)),
+        }
+    }
+
+    // Simulates the condition on everything the protocol's sockets receive
+    pub fn apply(self, protocol: &mut Protocol) {
+        if let Some(config) = self.to_config() {
+            protocol.link_condition(config);
+        }
+    }
+}
+
+// Reads a preset name, or custom values written as `latency_ms,jitter_ms,loss`
+impl FromStr for LinkCondition {
+    type Err = LinkConditionError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        if let Some(condition) = LinkCondition::preset(source) {
+            return Ok(condition);
+        }
+        let unknown = || LinkConditionError::UnknownPreset(source.to_string());
+        let values = source.split(',').map(str::trim).collect::<Vec<_>>();
+        let [latency_ms, jitter_ms, loss] = values[..] else { return Err(unknown()) };
+        let condition = LinkCondition::Custom {
+            latency_ms: latency_ms.parse().map_err(|_| unknown())?,
+            jitter_ms: jitter_ms.parse().map_err(|_| unknown())?,
+            loss: loss.parse().map_err(|_| unknown())?,
+        };
+        condition.validate()?;
+        Ok(condition)
+    }
+}
+
+impl fmt::Display for LinkCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LinkCondition::Perfect => return write!(f, "perfect"),
+            LinkCondition::Good => "good",
+            LinkCondition::Average => "average",
+            LinkCondition::Poor => "poor",
+            LinkCondition::Custom { .. } => "custom",
+        };
+        let Some(config) = self.to_config() else { return Ok(()) };
+        write!(
+            f,
+            "{} ({}±{} ms, {:.1}% loss)",
+            name,
+            config.incoming_latency,
+            config.incoming_jitter,
+            config.incoming_loss * 100.
+        )
+    }
+}
@@ -1,25 +1,50 @@
-use naia_bevy_shared::{EntityProperty, Message};
+use naia_bevy_shared::{EntityProperty, Message, Serde};
 
 use crate::inputs::InputAction;
 
+// Sent when connecting, with the name the player wants to go by and the server's token,
+// if it asks for one
+#[derive(Message)]
+pub struct AuthMessage {
+    pub name: String,
+    pub token: Option<String>,
+    // the session of a crab this client played before losing its connection
+    pub session_token: Option<String>,
+}
+
+impl AuthMessage {
+    pub fn new(name: String, token: Option<String>, session_token: Option<String>) -> Self {
+        AuthMessage {
+            name,
+            token,
+            session_token,
+        }
+    }
+}
+
 #[derive(Message)]
 pub struct PlayerAssignmentMessage {
     pub entity: EntityProperty,
+    // lets the client take back the same crab if it reconnects soon after disconnecting
+    pub session_token: String,
 }
 
 impl PlayerAssignmentMessage {
-    pub fn new() -> Self {
+    pub fn new(session_token: String) -> Self {
         PlayerAssignmentMessage {
             entity: EntityProperty::new_empty(),
+            session_token,
         }
     }
 }
 
-impl Default for PlayerAssignmentMessage {
-    fn default() -> Self {
-        Self::new()
-    }
-}
+// Tells a user that they are watching a match rather than playing in it
+#[derive(Message)]
+pub struct SpectatorAssignmentMessage;
+
+// Tells a spectator that a slot opened up in their room's lobby, so they can ready up
+#[derive(Message)]
+pub struct LobbyAssignmentMessage;
 
 #[derive(Message)]
 pub struct InputMessage {
@@ -35,3 +60,115 @@ impl InputMessage {
         }
     }
 }
+
+// A player's input, along with the tick that it is for, on its way to the other players. Clients
+// leave out the crab, which the server fills in with the sender's.
+#[derive(Message)]
+pub struct RelayedInputMessage {
+    pub entity: EntityProperty,
+    pub tick: u16,
+    pub action: InputAction,
+}
+
+impl RelayedInputMessage {
+    pub fn new(tick: u16, action: InputAction) -> Self {
+        RelayedInputMessage {
+            entity: EntityProperty::new_empty(),
+            tick,
+            action,
+        }
+    }
+}
+
+// A checksum of the obstacles in the rows from `first_row` up to (but not including) `end_row`
+#[derive(Message)]
+pub struct LevelChecksumMessage {
+    pub level: EntityProperty,
+    pub tick: u16,
+    pub first_row: i16,
+    pub end_row: i16,
+    pub checksum: u64,
+}
+
+impl LevelChecksumMessage {
+    pub fn new(tick: u16, first_row: i16, end_row: i16, checksum: u64) -> Self {
+        LevelChecksumMessage {
+            level: EntityProperty::new_empty(),
+            tick,
+            first_row,
+            end_row,
+            checksum,
+        }
+    }
+}
+
+// Sent by clients in the lobby to mark whether they are ready to play
+#[derive(Message)]
+pub struct ReadyMessage {
+    pub ready: bool,
+}
+
+impl ReadyMessage {
+    pub fn new(ready: bool) -> Self {
+        ReadyMessage { ready }
+    }
+}
+
+#[derive(Clone, PartialEq, Serde)]
+pub struct LobbyPlayer {
+    pub name: String,
+    pub ready: bool,
+}
+
+// Describes a room's lobby, for every user waiting in it
+#[derive(Message)]
+pub struct LobbyStateMessage {
+    pub players: Vec<LobbyPlayer>,
+    // the ticks left before the match starts, once anyone is ready
+    pub countdown_ticks: Option<u16>,
+}
+
+impl LobbyStateMessage {
+    pub fn new(players: Vec<LobbyPlayer>, countdown_ticks: Option<u16>) -> Self {
+        LobbyStateMessage {
+            players,
+            countdown_ticks,
+        }
+    }
+}
+
+// Asks the server for the top scores of a leaderboard, or of the requester's current level
+#[derive(Message)]
+pub struct LeaderboardRequestMessage {
+    pub leaderboard: Option<String>,
+}
+
+impl LeaderboardRequestMessage {
+    pub fn new(leaderboard: Option<String>) -> Self {
+        LeaderboardRequestMessage { leaderboard }
+    }
+}
+
+#[derive(Clone, PartialEq, Serde)]
+pub struct LeaderboardEntry {
+    pub name: String,
+    pub score: u16,
+    pub ticks: u16,
+    pub seed: u64,
+}
+
+// The top scores of a leaderboard, best first
+#[derive(Message)]
+pub struct LeaderboardResponseMessage {
+    pub leaderboard: String,
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+impl LeaderboardResponseMessage {
+    pub fn new(leaderboard: String, entries: Vec<LeaderboardEntry>) -> Self {
+        LeaderboardResponseMessage {
+            leaderboard,
+            entries,
+        }
+    }
+}
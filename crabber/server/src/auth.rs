@@ -0,0 +1,103 @@
+use std::fmt;
+
+use bevy_ecs::{
+    event::EventReader,
+    prelude::Resource,
+    system::{Res, ResMut},
+};
+use bevy_log::info;
+
+use naia_bevy_server::{events::AuthEvents, Server};
+
+use crabber_protocol::{
+    components::{validate_player_name, PlayerNameError},
+    messages::AuthMessage,
+};
+
+use crate::{sessions::Sessions, UserEntities};
+
+// What players must send to be let in
+#[derive(Resource, Default)]
+pub struct AuthConfig {
+    // a shared secret for private servers, which anyone may join when it is not set
+    pub token: Option<String>,
+}
+
+// Why a player was not let in.
+// naia does not send a reason with a rejection, so these are only logged on the server.
+#[derive(Debug)]
+pub enum AuthRejection {
+    InvalidToken,
+    InvalidName(PlayerNameError),
+    NameTaken(String),
+}
+
+impl fmt::Display for AuthRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthRejection::InvalidToken => write!(f, "the token is missing or wrong"),
+            AuthRejection::InvalidName(error) => write!(f, "the name is invalid: {}", error),
+            AuthRejection::NameTaken(name) => write!(f, "{} is already playing", name),
+        }
+    }
+}
+
+fn validate_auth(
+    message: &AuthMessage,
+    config: &AuthConfig,
+    user_entities: &UserEntities,
+    sessions: &Sessions,
+) -> Result<String, AuthRejection> {
+    if config.token.is_some() && message.token != config.token {
+        return Err(AuthRejection::InvalidToken);
+    }
+    // returning players keep the name their crab was held under
+    if let Some(name) = message
+        .session_token
+        .as_ref()
+        .and_then(|session_token| sessions.held_name(session_token))
+    {
+        // only one connection may take the crab back, and the name must not have been reused
+        let is_claimed = message
+            .session_token
+            .as_ref()
+            .is_some_and(|session_token| sessions.is_claimed(session_token));
+        if is_claimed || user_entities.get_user_by_name(name).is_some() {
+            return Err(AuthRejection::NameTaken(name.clone()));
+        }
+        return Ok(name.clone());
+    }
+    let name = validate_player_name(&message.name).map_err(AuthRejection::InvalidName)?;
+    if user_entities.get_user_by_name(&name).is_some() || sessions.is_name_held(&name) {
+        return Err(AuthRejection::NameTaken(name));
+    }
+    Ok(name)
+}
+
+pub fn auth_events(
+    mut server: Server,
+    mut user_entities: ResMut<UserEntities>,
+    mut sessions: ResMut<Sessions>,
+    config: Res<AuthConfig>,
+    mut event_reader: EventReader<AuthEvents>,
+) {
+    for events in event_reader.iter() {
+        for (user_key, message) in events.read::<AuthMessage>() {
+            match validate_auth(&message, &config, &user_entities, &sessions) {
+                Ok(name) => {
+                    info!("Accepted {}", name);
+                    if let Some(session_token) = message.session_token {
+                        sessions.claim(user_key, session_token);
+                    }
+                    // names are claimed straight away, so two players cannot both take one
+                    user_entities.insert_name(user_key, name);
+                    server.accept_connection(&user_key);
+                }
+                Err(rejection) => {
+                    info!("Rejected a connection: {}", rejection);
+                    server.reject_connection(&user_key);
+                }
+            }
+        }
+    }
+}
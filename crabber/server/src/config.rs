@@ -0,0 +1,176 @@
+use std::{fmt, net::SocketAddr, path::Path};
+
+use bevy_ecs::prelude::Resource;
+use serde::Deserialize;
+
+use naia_bevy_server::transport::Socket;
+
+use crabber_protocol::link_condition::{LinkCondition, LinkConditionError};
+
+// Where the server listens, unless it is configured otherwise
+pub const DEFAULT_SIGNALING_ADDRESS: &str = "127.0.0.1:14191";
+pub const DEFAULT_WEBRTC_ADDRESS: &str = "127.0.0.1:14192";
+pub const DEFAULT_UDP_ADDRESS: &str = "127.0.0.1:14193";
+
+// The addresses the server listens on, and the one it tells clients to send data to
+#[derive(Resource, Clone)]
+pub struct NetworkConfig {
+    // where clients start their WebRTC handshake
+    pub signaling_address: SocketAddr,
+    // where WebRTC data channels are received
+    pub webrtc_address: SocketAddr,
+    // the data address as clients reach it, which differs from `webrtc_address` behind NAT
+    pub public_webrtc_url: String,
+    // where native clients connect over plain UDP, when the server is built with `transport_udp`
+    pub udp_address: SocketAddr,
+}
+
+impl NetworkConfig {
+    // Advertises the data address itself, which suits servers that are not behind NAT
+    pub fn new(signaling_address: SocketAddr, webrtc_address: SocketAddr) -> Self {
+        NetworkConfig {
+            signaling_address,
+            webrtc_address,
+            public_webrtc_url: format!("http://{}", webrtc_address),
+            udp_address: DEFAULT_UDP_ADDRESS
+                .parse()
+                .expect("could not parse UDP address/port"),
+        }
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig::new(
+            DEFAULT_SIGNALING_ADDRESS
+                .parse()
+                .expect("could not parse Signaling address/port"),
+            DEFAULT_WEBRTC_ADDRESS
+                .parse()
+                .expect("could not parse WebRTC data address/port"),
+        )
+    }
+}
+
+// A socket for the server to listen on in place of its configured addresses, which lets tests
+// run the server without a network. It is taken when the server starts.
+#[derive(Resource)]
+pub struct ListenSocket(pub Option<Box<dyn Socket + Send + Sync>>);
+
+impl ListenSocket {
+    pub fn new(socket: impl Socket + Send + Sync + 'static) -> Self {
+        ListenSocket(Some(Box::new(socket)))
+    }
+}
+
+// How many lives crabs get, as written in a config file
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum LivesSetting {
+    Limited(u16),
+    Unlimited,
+}
+
+impl LivesSetting {
+    pub fn to_lives(self) -> Option<u16> {
+        match self {
+            LivesSetting::Limited(count) => Some(count),
+            LivesSetting::Unlimited => None,
+        }
+    }
+}
+
+// The server's settings, as written in a RON file:
+//
+// (
+//     signaling_address: Some("0.0.0.0:14191"),
+//     webrtc_address: Some("0.0.0.0:14192"),
+//     public_webrtc_url: Some("http://203.0.113.7:14192"),
+//     udp_address: Some("0.0.0.0:14193"),
+//     tick_interval_ms: Some(16),
+//     link_condition: Some(Custom(latency_ms: 120, jitter_ms: 30, loss: 0.05)),
+//     max_players: Some(4),
+//     max_rooms: Some(8),
+//     lives: Some(Limited(3)),
+//     playlist: Some("levels/campaign.ron"),
+// )
+//
+// Every setting is optional, and flags on the command line take precedence over the file.
+// Level and playlist paths are relative to the config file.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfigFile {
+    pub signaling_address: Option<SocketAddr>,
+    pub webrtc_address: Option<SocketAddr>,
+    pub public_webrtc_url: Option<String>,
+    pub udp_address: Option<SocketAddr>,
+    pub tick_interval_ms: Option<u64>,
+    pub link_condition: Option<LinkCondition>,
+    pub max_players: Option<usize>,
+    // without a limit, a new room is opened whenever every room is full
+    pub max_rooms: Option<usize>,
+    pub lives: Option<LivesSetting>,
+    pub respawn_ticks: Option<u16>,
+    pub knockout_penalty: Option<u16>,
+    pub reconnect_ticks: Option<u16>,
+    // a preset name or the path to a difficulty config file
+    pub difficulty: Option<String>,
+    pub levels: Vec<String>,
+    pub playlist: Option<String>,
+    pub leaderboard: Option<String>,
+    pub token: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ServerConfigError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Invalid(&'static str),
+}
+
+impl fmt::Display for ServerConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerConfigError::Io(error) => write!(f, "could not read config: {}", error),
+            ServerConfigError::Parse(error) => write!(f, "could not parse config: {}", error),
+            ServerConfigError::Invalid(reason) => write!(f, "invalid config: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ServerConfigError {}
+
+impl ServerConfigFile {
+    pub fn from_ron(source: &str) -> Result<Self, ServerConfigError> {
+        let config: ServerConfigFile = ron::from_str(source).map_err(ServerConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ServerConfigError> {
+        let source = std::fs::read_to_string(path).map_err(ServerConfigError::Io)?;
+        ServerConfigFile::from_ron(&source)
+    }
+
+    pub fn validate(&self) -> Result<(), ServerConfigError> {
+        if self.max_players == Some(0) {
+            return Err(ServerConfigError::Invalid("max_players must be positive"));
+        }
+        if self.max_rooms == Some(0) {
+            return Err(ServerConfigError::Invalid("max_rooms must be positive"));
+        }
+        if self.tick_interval_ms == Some(0) {
+            return Err(ServerConfigError::Invalid(
+                "tick_interval_ms must be positive",
+            ));
+        }
+        if let Some(link_condition) = self.link_condition {
+            if let Err(LinkConditionError::Invalid(reason)) = link_condition.validate() {
+                return Err(ServerConfigError::Invalid(reason));
+            }
+        }
+        if let Some(LivesSetting::Limited(0)) = self.lives {
+            return Err(ServerConfigError::Invalid("lives must be positive"));
+        }
+        Ok(())
+    }
+}
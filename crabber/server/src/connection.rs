@@ -1,104 +1,226 @@
 use bevy_ecs::{
+    entity::Entity,
     event::EventReader,
-    system::{Commands, Query, ResMut},
+    query::Without,
+    system::{Commands, Query, Res, ResMut},
 };
 use bevy_log::info;
 
 use naia_bevy_server::{
     events::{ConnectEvent, DisconnectEvent, ErrorEvent},
-    CommandsExt, Server,
+    CommandsExt, RoomKey, Server, UserKey,
 };
 
 use crabber_protocol::{
     bundles::CrabBundle,
     channels::PlayerAssignmentChannel,
-    components::{Controlled, Crab, Level},
-    messages::PlayerAssignmentMessage,
+    components::{Controlled, Crab, Level, OnLevel, PlayerName},
+    messages::{LobbyAssignmentMessage, PlayerAssignmentMessage, SpectatorAssignmentMessage},
 };
 
-use crate::UserEntities;
+use crate::{
+    rooms::{MatchRooms, RoomPhase},
+    rounds::despawn_level,
+    sessions::{SessionConfig, Sessions},
+    UserEntities,
+};
+
+fn send_player_assignment(server: &mut Server, user_key: &UserKey, entity: &Entity, token: String) {
+    let mut assignment_message = PlayerAssignmentMessage::new(token);
+    assignment_message.entity.set(server, entity);
+    server.send_message::<PlayerAssignmentChannel, PlayerAssignmentMessage>(
+        user_key,
+        &assignment_message,
+    );
+}
 
 pub fn connect_events(
-    mut commands: Commands,
     mut server: Server,
+    mut match_rooms: ResMut<MatchRooms>,
     mut user_entities: ResMut<UserEntities>,
+    mut sessions: ResMut<Sessions>,
     mut event_reader: EventReader<ConnectEvent>,
-    players_query: Query<&Crab>,
 ) {
     for ConnectEvent(user_key) in event_reader.iter() {
-        let room_key = server
-            .room_keys()
-            .into_iter()
-            .next()
-            .unwrap_or_else(|| server.make_room().key());
-
-        let address = server.user_mut(user_key).enter_room(&room_key).address();
-
-        info!("Client connected from: {}", address);
-
-        let num_players = players_query.into_iter().count();
-
-        // spawn a level if we are about to spawn the second player
-        if num_players == 0 {
-            let level = Level::new_random();
-            let (car_bundles, raft_bundles) = level.create_level_bundles();
-            for bundle in car_bundles.into_iter() {
-                let entity = commands
-                    .spawn((bundle, Controlled))
-                    .enable_replication(&mut server)
-                    .id();
-                server.room_mut(&room_key).add_entity(&entity);
+        if let Some(held) = sessions.take_reconnect(user_key) {
+            // the slot was kept for this player, so they go straight back into their match
+            info!("{} reconnected", held.name);
+            server.user_mut(user_key).enter_room(&held.room_key);
+            match_rooms.add_player(held.room_key, *user_key);
+            if let Some(room) = match_rooms.get_mut(&held.room_key) {
+                room.reserved_slots = room.reserved_slots.saturating_sub(1);
             }
-            for bundle in raft_bundles.into_iter() {
-                let entity = commands
-                    .spawn((bundle, Controlled))
-                    .enable_replication(&mut server)
-                    .id();
-                server.room_mut(&room_key).add_entity(&entity);
+            // without a crab, which is let go when the round ends, one is spawned with the others
+            if let Some(entity) = held.entity {
+                user_entities.insert(*user_key, entity);
+                let token = sessions.token_for(user_key);
+                send_player_assignment(&mut server, user_key, &entity, token);
             }
-            let entity = commands.spawn(level).enable_replication(&mut server).id();
-            server.room_mut(&room_key).add_entity(&entity);
+        } else if let Some(room_key) = match_rooms.find_open_room() {
+            server.user_mut(user_key).enter_room(&room_key);
+            match_rooms.add_player(room_key, *user_key);
+        } else if match_rooms.can_make_room() {
+            let room_key = server.make_room().key();
+            match_rooms.insert_room(room_key);
+            server.user_mut(user_key).enter_room(&room_key);
+            match_rooms.add_player(room_key, *user_key);
+        } else if let Some(room_key) = match_rooms.find_room_to_spectate() {
+            // every room is full and no more can be opened, so watch a match until a slot frees up
+            server.user_mut(user_key).enter_room(&room_key);
+            match_rooms.add_spectator(room_key, *user_key);
+            server.send_message::<PlayerAssignmentChannel, SpectatorAssignmentMessage>(
+                user_key,
+                &SpectatorAssignmentMessage,
+            );
+            info!("Client is spectating");
         }
 
-        // only spawn player entities for the first two players
-        if num_players < 2 {
+        info!("Client connected from: {}", server.user(user_key).address());
+    }
+}
+
+// Spawns crabs for players whose room has a level ready for them
+pub fn spawn_players(
+    mut commands: Commands,
+    mut server: Server,
+    mut user_entities: ResMut<UserEntities>,
+    mut sessions: ResMut<Sessions>,
+    match_rooms: Res<MatchRooms>,
+    level_query: Query<&Level>,
+) {
+    for (room_key, room) in match_rooms.iter() {
+        let Some(level_entity) = room.level else { continue };
+        let Ok(level) = level_query.get(level_entity) else { continue };
+        for user_key in room.players.iter() {
+            if user_entities.get_entity(user_key).is_some() || !server.user_exists(user_key) {
+                continue;
+            }
+            let name = user_entities
+                .get_name(user_key)
+                .cloned()
+                .unwrap_or_default();
             let entity = commands
-                .spawn((CrabBundle::new(), Controlled))
+                .spawn((
+                    CrabBundle::new(level),
+                    PlayerName::new(name),
+                    OnLevel(level_entity),
+                    Controlled,
+                ))
                 .enable_replication(&mut server)
                 .id();
 
-            server.room_mut(&room_key).add_entity(&entity);
+            server.room_mut(room_key).add_entity(&entity);
             user_entities.insert(*user_key, entity);
 
-            let mut assignment_message = PlayerAssignmentMessage::new();
-            assignment_message.entity.set(&server, &entity);
-            server.send_message::<PlayerAssignmentChannel, PlayerAssignmentMessage>(
+            let token = sessions.token_for(user_key);
+            send_player_assignment(&mut server, user_key, &entity, token);
+        }
+    }
+}
+
+// Moves spectators into any slots that opened up in a room. During a match their crabs are
+// spawned along with the other players', while in a lobby they are sent to it to ready up.
+pub(crate) fn promote_spectators(
+    server: &mut Server,
+    match_rooms: &mut MatchRooms,
+    room_key: &RoomKey,
+) {
+    let promoted = match_rooms.promote_spectators(room_key);
+    if promoted.is_empty() {
+        return;
+    }
+    info!("Promoted {} spectators to players", promoted.len());
+    let Some(room) = match_rooms.get_mut(room_key) else { return };
+    if room.phase == RoomPhase::Lobby {
+        for user_key in promoted.iter() {
+            server.send_message::<PlayerAssignmentChannel, LobbyAssignmentMessage>(
                 user_key,
-                &assignment_message,
+                &LobbyAssignmentMessage,
             );
         }
+        room.lobby_changed = true;
     }
 }
 
+// Closes a room once everyone has left it, since its match is over
+pub(crate) fn close_room_if_empty(
+    commands: &mut Commands,
+    server: &mut Server,
+    match_rooms: &mut MatchRooms,
+    room_key: &RoomKey,
+    obstacles_query: &Query<(Entity, &OnLevel), Without<Crab>>,
+) {
+    let is_empty = match_rooms
+        .get(room_key)
+        .is_some_and(|room| room.is_empty());
+    if !is_empty {
+        return;
+    }
+    if let Some(room) = match_rooms.remove_room(room_key) {
+        info!("Closing an empty room");
+        if let Some(level_entity) = room.level {
+            despawn_level(commands, level_entity, obstacles_query);
+        }
+    }
+    server.room_mut(room_key).destroy();
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn disconnect_events(
     mut commands: Commands,
     mut server: Server,
     mut user_entities: ResMut<UserEntities>,
+    mut match_rooms: ResMut<MatchRooms>,
+    mut sessions: ResMut<Sessions>,
+    session_config: Res<SessionConfig>,
     mut event_reader: EventReader<DisconnectEvent>,
+    obstacles_query: Query<(Entity, &OnLevel), Without<Crab>>,
 ) {
     for DisconnectEvent(user_key, user) in event_reader.iter() {
         info!("Crabber Server disconnected from: {:?}", user.address);
+        let name = user_entities.remove_name(user_key).unwrap_or_default();
 
+        let room_key = match_rooms.remove_player(user_key);
+        let is_playing = room_key
+            .and_then(|room_key| match_rooms.get(&room_key))
+            .is_some_and(|room| room.phase == RoomPhase::Playing);
         if let Some(entity) = user_entities.remove(user_key) {
-            let room_keys = {
-                let user_ref = server.user(user_key);
-                user_ref.room_keys().map(|key| *key).collect::<Vec<_>>()
+            // players who drop out mid-match keep their crab and slot for a while,
+            // in case they reconnect
+            let is_held = match room_key {
+                Some(room_key) if is_playing => sessions.hold(
+                    user_key,
+                    name.clone(),
+                    room_key,
+                    entity,
+                    session_config.grace_ticks,
+                ),
+                _ => false,
             };
-            for room_key in room_keys.into_iter() {
+            if is_held {
+                info!("Holding the crab of {} until they reconnect", name);
+                if let Some(room) = room_key.and_then(|room_key| match_rooms.get_mut(&room_key)) {
+                    room.reserved_slots += 1;
+                }
+                sessions.remove_user(user_key);
+                continue;
+            }
+            if let Some(room_key) = room_key {
                 server.room_mut(&room_key).remove_entity(&entity);
             }
             commands.entity(entity).despawn();
         }
+        sessions.remove_user(user_key);
+
+        let Some(room_key) = room_key else { continue };
+        promote_spectators(&mut server, &mut match_rooms, &room_key);
+        close_room_if_empty(
+            &mut commands,
+            &mut server,
+            &mut match_rooms,
+            &room_key,
+            &obstacles_query,
+        );
     }
 }
 
@@ -1,21 +1,67 @@
+use bevy_ecs::system::{Commands, Res, ResMut};
 use bevy_log::info;
 
-use naia_bevy_server::{transport::webrtc, Server};
+use naia_bevy_server::{
+    transport::{webrtc, Socket},
+    Server,
+};
 
-pub fn init(mut server: Server) {
+use crate::config::{ListenSocket, NetworkConfig};
+
+pub fn init(
+    mut commands: Commands,
+    mut server: Server,
+    network_config: Res<NetworkConfig>,
+    listen_socket: Option<ResMut<ListenSocket>>,
+) {
     info!("Starting Crabber server");
 
+    if let Some(socket) = listen_socket.and_then(|mut listen_socket| listen_socket.0.take()) {
+        let socket: Box<dyn Socket> = socket;
+        server.listen(socket);
+        return;
+    }
+
     let server_addresses = webrtc::ServerAddrs::new(
-        "127.0.0.1:14191"
-            .parse()
-            .expect("could not parse Signaling address/port"),
+        network_config.signaling_address,
         // IP Address to listen on for UDP WebRTC data channels
-        "127.0.0.1:14192"
-            .parse()
-            .expect("could not parse WebRTC data address/port"),
+        network_config.webrtc_address,
         // The public WebRTC IP address to advertise
-        "http://127.0.0.1:14192",
+        &network_config.public_webrtc_url,
     );
     let socket = webrtc::Socket::new(&server_addresses, server.socket_config());
+    listen(&mut commands, &mut server, socket, &network_config);
+}
+
+#[cfg(not(feature = "transport_udp"))]
+fn listen(
+    _commands: &mut Commands,
+    server: &mut Server,
+    socket: webrtc::Socket,
+    _network_config: &NetworkConfig,
+) {
+    server.listen(socket);
+}
+
+// Native clients connect over plain UDP, while browsers keep using WebRTC
+#[cfg(feature = "transport_udp")]
+fn listen(
+    commands: &mut Commands,
+    server: &mut Server,
+    socket: webrtc::Socket,
+    network_config: &NetworkConfig,
+) {
+    use naia_bevy_server::transport::udp;
+
+    use crate::transport::DualSocket;
+
+    info!(
+        "Listening for UDP clients on {}",
+        network_config.udp_address
+    );
+    let link_condition = server.socket_config().link_condition.clone();
+    let udp_socket = udp::Socket::new(&network_config.udp_address, link_condition);
+    let socket = DualSocket::new(socket, udp_socket);
+    commands.insert_resource(socket.addresses());
     server.listen(socket);
 }
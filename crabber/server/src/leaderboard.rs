@@ -0,0 +1,223 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy_ecs::{
+    event::EventReader,
+    prelude::Resource,
+    query::With,
+    system::{Query, Res, ResMut},
+};
+use bevy_log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use naia_bevy_server::{events::MessageEvents, Server};
+
+use crabber_protocol::{
+    channels::LeaderboardChannel,
+    components::{Crab, Level, MatchPhase, MatchState, OnLevel, PlayerName, Score, Winner},
+    messages::{LeaderboardEntry, LeaderboardRequestMessage, LeaderboardResponseMessage},
+};
+
+use crate::rooms::MatchRooms;
+
+// Where the leaderboard is kept, unless the server is configured otherwise
+pub const DEFAULT_LEADERBOARD_PATH: &str = "crabber-leaderboard.jsonl";
+// how many scores are sent for each leaderboard request
+const TOP_SCORES_LIMIT: usize = 10;
+
+// How a single player did in a finished round
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlayerRecord {
+    pub name: String,
+    pub score: u16,
+    pub row_points: u16,
+    pub finish_bonus: u16,
+    pub time_bonus: u16,
+    pub penalty: u16,
+    // how long the player took to finish, or how long they played for if they did not
+    pub ticks: u16,
+    pub finished: bool,
+}
+
+// A finished round, which is stored as a single line of JSON
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MatchRecord {
+    // seconds since the unix epoch
+    pub finished_at: u64,
+    // the level or difficulty that the round's scores are ranked against
+    pub leaderboard: String,
+    pub seed: u64,
+    pub round: u16,
+    pub players: Vec<PlayerRecord>,
+    pub winner: Option<String>,
+}
+
+// The history of finished rounds, which is appended to a JSON-lines file as rounds finish
+#[derive(Resource, Default)]
+pub struct Leaderboard {
+    // rounds are only kept in memory when there is no file
+    path: Option<PathBuf>,
+    records: Vec<MatchRecord>,
+}
+
+impl Leaderboard {
+    // Opens the leaderboard file at the given path, creating it when it does not exist yet.
+    // Lines that cannot be read are skipped, so that one bad write does not lose the history.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut records = Vec::new();
+        match File::open(path) {
+            Ok(file) => {
+                for (index, line) in BufReader::new(file).lines().enumerate() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    // this runs before logging is set up, so problems go straight to stderr
+                    match serde_json::from_str::<MatchRecord>(&line) {
+                        Ok(record) => records.push(record),
+                        Err(error) => eprintln!(
+                            "Skipping line {} of {}: {}",
+                            index + 1,
+                            path.display(),
+                            error
+                        ),
+                    }
+                }
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+        Ok(Leaderboard {
+            path: Some(path.to_path_buf()),
+            records,
+        })
+    }
+
+    pub fn record(&mut self, record: MatchRecord) -> io::Result<()> {
+        if let Some(path) = self.path.as_ref() {
+            let mut line = serde_json::to_string(&record)?;
+            line.push('\n');
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?
+                .write_all(line.as_bytes())?;
+        }
+        self.records.push(record);
+        Ok(())
+    }
+
+    // The best scores on a leaderboard, with the quickest first among equal scores
+    pub fn top_scores(&self, leaderboard: &str, limit: usize) -> Vec<LeaderboardEntry> {
+        let mut entries = self
+            .records
+            .iter()
+            .filter(|record| record.leaderboard == leaderboard)
+            .flat_map(|record| {
+                record.players.iter().map(|player| LeaderboardEntry {
+                    name: player.name.clone(),
+                    score: player.score,
+                    ticks: player.ticks,
+                    seed: record.seed,
+                })
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| b.score.cmp(&a.score).then(a.ticks.cmp(&b.ticks)));
+        entries.truncate(limit);
+        entries
+    }
+}
+
+fn now_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+// Records each round once it is over
+pub fn record_finished_rounds(
+    mut match_rooms: ResMut<MatchRooms>,
+    mut leaderboard: ResMut<Leaderboard>,
+    level_query: Query<(&Level, &MatchState)>,
+    // players whose crab is held for them while they reconnect are recorded too
+    crab_query: Query<(&OnLevel, &PlayerName, &Score, Option<&Winner>), With<Crab>>,
+) {
+    for (_, room) in match_rooms.iter_mut() {
+        if room.is_round_recorded {
+            continue;
+        }
+        let Some(level_entity) = room.level else { continue };
+        let Ok((level, match_state)) = level_query.get(level_entity) else { continue };
+        if !matches!(
+            *match_state.phase,
+            MatchPhase::RoundOver | MatchPhase::Results
+        ) {
+            continue;
+        }
+        room.is_round_recorded = true;
+
+        let mut winner = None;
+        let mut players = Vec::new();
+        for (OnLevel(crab_level), name, score, is_winner) in crab_query.iter() {
+            if *crab_level != level_entity {
+                continue;
+            }
+            let name = (*name.name).clone();
+            if is_winner.is_some() {
+                winner = Some(name.clone());
+            }
+            players.push(PlayerRecord {
+                name,
+                score: *score.value,
+                row_points: *score.row_points,
+                finish_bonus: *score.finish_bonus,
+                time_bonus: *score.time_bonus,
+                penalty: *score.penalty,
+                ticks: *score.elapsed_ticks,
+                finished: score.has_finished(),
+            });
+        }
+
+        let record = MatchRecord {
+            finished_at: now_seconds(),
+            leaderboard: room.leaderboard.clone(),
+            seed: *level.seed,
+            round: *match_state.round,
+            players,
+            winner,
+        };
+        info!("Recording round {} on {}", record.round, record.leaderboard);
+        if let Err(error) = leaderboard.record(record) {
+            warn!("Failed to save the round to the leaderboard: {}", error);
+        }
+    }
+}
+
+// Answers requests for the top scores of a leaderboard, which default to the requester's own
+pub fn receive_leaderboard_requests(
+    mut server: Server,
+    mut event_reader: EventReader<MessageEvents>,
+    match_rooms: Res<MatchRooms>,
+    leaderboard: Res<Leaderboard>,
+) {
+    for events in event_reader.iter() {
+        for (user_key, request) in events.read::<LeaderboardChannel, LeaderboardRequestMessage>() {
+            let name = request.leaderboard.or_else(|| {
+                let room_key = match_rooms.get_user_room(&user_key)?;
+                Some(match_rooms.get(room_key)?.leaderboard.clone())
+            });
+            let Some(name) = name else { continue };
+            let entries = leaderboard.top_scores(&name, TOP_SCORES_LIMIT);
+            server.send_message::<LeaderboardChannel, LeaderboardResponseMessage>(
+                &user_key,
+                &LeaderboardResponseMessage::new(name, entries),
+            );
+        }
+    }
+}
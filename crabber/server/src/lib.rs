@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use bevy_app::{App, Plugin};
 use bevy_ecs::schedule::{IntoSystemConfigs, IntoSystemSetConfig, SystemSet};
 use bevy_ecs::{entity::Entity, prelude::Resource};
@@ -7,20 +9,55 @@ use naia_bevy_server::UserKey;
 use naia_bevy_server::{Plugin as ServerPlugin, ReceiveEvents, ServerConfig};
 
 use crabber_core::TickPlugin;
-use crabber_protocol::protocol;
+use crabber_protocol::{
+    components::RespawnConfig, link_condition::LinkCondition, protocol, DEFAULT_TICK_INTERVAL,
+};
 
+pub mod auth;
+pub mod config;
 pub mod connection;
 pub mod init;
+pub mod leaderboard;
+pub mod lobby;
+pub mod playlist;
+pub mod rooms;
+pub mod rounds;
+pub mod sessions;
 pub mod tick;
+#[cfg(feature = "transport_udp")]
+pub mod transport;
 
 #[derive(Resource, Default)]
 pub struct UserEntities {
     user_to_entity_map: HashMap<UserKey, Entity>,
     entity_to_user_map: HashMap<Entity, UserKey>,
+    user_to_name_map: HashMap<UserKey, String>,
+    name_to_user_map: HashMap<String, UserKey>,
 }
 
 impl UserEntities {
-    #[allow(dead_code)]
+    fn get_name(&self, user: &UserKey) -> Option<&String> {
+        self.user_to_name_map.get(user)
+    }
+
+    fn get_user_by_name(&self, name: &str) -> Option<&UserKey> {
+        self.name_to_user_map.get(name)
+    }
+
+    fn insert_name(&mut self, user_key: UserKey, name: String) {
+        self.name_to_user_map.insert(name.clone(), user_key);
+        self.user_to_name_map.insert(user_key, name);
+    }
+
+    fn remove_name(&mut self, user: &UserKey) -> Option<String> {
+        self.user_to_name_map.remove(user).inspect(|name| {
+            // the name may belong to someone else by now
+            if self.name_to_user_map.get(name) == Some(user) {
+                self.name_to_user_map.remove(name);
+            }
+        })
+    }
+
     fn get_entity(&self, user: &UserKey) -> Option<&Entity> {
         self.user_to_entity_map.get(user)
     }
@@ -36,9 +73,8 @@ impl UserEntities {
     }
 
     fn remove(&mut self, user: &UserKey) -> Option<Entity> {
-        self.user_to_entity_map.remove(user).and_then(|entity| {
-            self.entity_to_user_map.remove(&entity);
-            Some(entity)
+        self.user_to_entity_map.remove(user).inspect(|entity| {
+            self.entity_to_user_map.remove(entity);
         })
     }
 }
@@ -46,30 +82,86 @@ impl UserEntities {
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, SystemSet)]
 struct TickSet;
 
-pub struct CrabberServerPlugin;
+pub struct CrabberServerPlugin {
+    pub tick_interval: Duration,
+    // the network conditions simulated on packets from clients
+    pub link_condition: LinkCondition,
+}
+
+impl CrabberServerPlugin {
+    pub fn new(tick_interval: Duration, link_condition: LinkCondition) -> Self {
+        CrabberServerPlugin {
+            tick_interval,
+            link_condition,
+        }
+    }
+}
+
+impl Default for CrabberServerPlugin {
+    fn default() -> Self {
+        CrabberServerPlugin::new(DEFAULT_TICK_INTERVAL, LinkCondition::default())
+    }
+}
 
 impl Plugin for CrabberServerPlugin {
     fn build(&self, app: &mut App) {
+        let mut protocol = protocol();
+        protocol.tick_interval(self.tick_interval);
+        self.link_condition.apply(&mut protocol);
         app.add_plugin(ServerPlugin::new(
             ServerConfig {
-                require_auth: false,
+                require_auth: true,
                 ..Default::default()
             },
-            protocol(),
+            protocol,
         ))
         .configure_set(TickSet.in_set(ReceiveEvents))
         .init_resource::<UserEntities>()
+        .init_resource::<playlist::LevelPlaylist>()
+        .init_resource::<rooms::MatchRooms>()
+        .init_resource::<RespawnConfig>()
+        .init_resource::<leaderboard::Leaderboard>()
+        .init_resource::<auth::AuthConfig>()
+        .init_resource::<sessions::Sessions>()
+        .init_resource::<sessions::SessionConfig>()
+        .init_resource::<config::NetworkConfig>()
         .add_startup_system(init::init)
         .add_plugin(TickPlugin::new(TickSet, tick::tick_events))
         .add_systems(
             (
+                auth::auth_events,
                 connection::connect_events,
+                lobby::receive_ready_messages,
+                tick::relay_inputs,
+                leaderboard::receive_leaderboard_requests,
+                lobby::tick_lobbies,
+                connection::spawn_players,
                 connection::disconnect_events,
+                sessions::expire_held_players,
                 connection::error_events,
             )
+                .chain()
                 .in_set(ReceiveEvents)
                 .before(TickSet),
         )
-        .add_system(tick::update_entity_scopes);
+        .add_systems(
+            (
+                tick::update_entity_scopes,
+                tick::send_level_checksums,
+                leaderboard::record_finished_rounds,
+                rounds::start_next_rounds,
+            )
+                .chain(),
+        );
+        #[cfg(feature = "transport_udp")]
+        {
+            use bevy_ecs::schedule::IntoSystemConfig;
+
+            app.add_system(
+                transport::forget_disconnected_addresses
+                    .in_set(ReceiveEvents)
+                    .before(TickSet),
+            );
+        }
     }
 }
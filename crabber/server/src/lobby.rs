@@ -0,0 +1,133 @@
+use bevy_ecs::{
+    event::EventReader,
+    system::{Commands, Res, ResMut},
+};
+use bevy_log::info;
+
+use naia_bevy_server::{
+    events::{MessageEvents, TickEvent},
+    RoomKey, Server,
+};
+
+use crabber_protocol::{
+    channels::LobbyChannel,
+    components::RespawnConfig,
+    messages::{LobbyStateMessage, ReadyMessage},
+};
+
+use crate::{
+    playlist::LevelPlaylist,
+    rooms::{MatchRoom, MatchRooms, RoomPhase},
+    rounds::spawn_level,
+    UserEntities,
+};
+
+// how long a lobby waits for everyone to ready up, once anyone is ready (about 10 seconds)
+const LOBBY_COUNTDOWN_TICKS: u16 = 600;
+// how often the countdown is sent to players while it runs
+const COUNTDOWN_BROADCAST_INTERVAL_TICKS: u16 = 60;
+
+pub fn receive_ready_messages(
+    mut event_reader: EventReader<MessageEvents>,
+    mut match_rooms: ResMut<MatchRooms>,
+) {
+    for events in event_reader.iter() {
+        for (user_key, message) in events.read::<LobbyChannel, ReadyMessage>() {
+            let Some(room_key) = match_rooms.get_user_room(&user_key).copied() else { continue };
+            let Some(room) = match_rooms.get_mut(&room_key) else { continue };
+            if room.phase != RoomPhase::Lobby {
+                continue;
+            }
+            if message.ready {
+                room.ready.insert(user_key);
+            } else {
+                room.ready.remove(&user_key);
+            }
+            room.lobby_changed = true;
+        }
+    }
+}
+
+pub fn tick_lobbies(
+    mut commands: Commands,
+    mut server: Server,
+    mut tick_reader: EventReader<TickEvent>,
+    mut match_rooms: ResMut<MatchRooms>,
+    mut playlist: ResMut<LevelPlaylist>,
+    respawn_config: Res<RespawnConfig>,
+    user_entities: Res<UserEntities>,
+) {
+    for _ in tick_reader.iter() {
+        for (room_key, room) in match_rooms.iter_mut() {
+            if room.phase != RoomPhase::Lobby {
+                continue;
+            }
+
+            // the countdown runs for as long as anyone is ready
+            let countdown_ticks = if room.ready.is_empty() {
+                None
+            } else {
+                Some(
+                    room.countdown_ticks
+                        .map_or(LOBBY_COUNTDOWN_TICKS, |ticks| ticks.saturating_sub(1)),
+                )
+            };
+            if countdown_ticks.is_some() != room.countdown_ticks.is_some()
+                || countdown_ticks
+                    .is_some_and(|ticks| ticks % COUNTDOWN_BROADCAST_INTERVAL_TICKS == 0)
+            {
+                room.lobby_changed = true;
+            }
+            room.countdown_ticks = countdown_ticks;
+
+            if room.is_everyone_ready() || room.countdown_ticks == Some(0) {
+                start_match(
+                    &mut commands,
+                    &mut server,
+                    room_key,
+                    room,
+                    &mut playlist,
+                    *respawn_config,
+                );
+                continue;
+            }
+
+            if room.lobby_changed {
+                room.lobby_changed = false;
+                server
+                    .room_mut(room_key)
+                    .broadcast_message::<LobbyChannel, LobbyStateMessage>(
+                        &room.lobby_state_message(&user_entities),
+                    );
+            }
+        }
+    }
+}
+
+fn start_match(
+    commands: &mut Commands,
+    server: &mut Server,
+    room_key: &RoomKey,
+    room: &mut MatchRoom,
+    playlist: &mut LevelPlaylist,
+    respawn_config: RespawnConfig,
+) {
+    let (level, leaderboard) = playlist.next_level();
+    info!(
+        "Starting a match for {} players with level seed {}",
+        room.players.len(),
+        *level.seed
+    );
+    room.level = Some(spawn_level(
+        commands,
+        server,
+        room_key,
+        level,
+        1,
+        respawn_config,
+    ));
+    room.leaderboard = leaderboard;
+    room.is_round_recorded = false;
+    room.phase = RoomPhase::Playing;
+    room.countdown_ticks = None;
+}
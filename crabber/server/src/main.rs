@@ -1,21 +1,310 @@
-use std::time::Duration;
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
 use bevy_app::{App, ScheduleRunnerPlugin, ScheduleRunnerSettings};
 use bevy_core::{FrameCountPlugin, TaskPoolPlugin, TypeRegistrationPlugin};
 use bevy_log::{info, LogPlugin};
 
-use crabber_server::CrabberServerPlugin;
+use crabber_protocol::{
+    components::RespawnConfig,
+    generation::{LevelGenConfig, DIFFICULTY_ENV_VAR},
+    link_condition::LinkCondition,
+    DEFAULT_TICK_INTERVAL,
+};
+use crabber_server::{
+    auth::AuthConfig,
+    config::{LivesSetting, NetworkConfig, ServerConfigFile},
+    leaderboard::{Leaderboard, DEFAULT_LEADERBOARD_PATH},
+    playlist::{LevelPlaylist, PlaylistLevel},
+    rooms::{MatchRooms, DEFAULT_MAX_PLAYERS_PER_ROOM},
+    sessions::SessionConfig,
+    CrabberServerPlugin,
+};
+
+// Everything the command line configures, which is inserted as resources before the server starts
+struct ServerArgs {
+    tick_interval: Duration,
+    link_condition: LinkCondition,
+    network_config: NetworkConfig,
+    playlist: LevelPlaylist,
+    match_rooms: MatchRooms,
+    respawn_config: RespawnConfig,
+    leaderboard: Leaderboard,
+    auth_config: AuthConfig,
+    session_config: SessionConfig,
+}
+
+fn exit_with_error(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+fn read_value(args: &mut impl Iterator<Item = String>, flag: &str, expected: &str) -> String {
+    match args.next() {
+        Some(value) => value,
+        None => exit_with_error(format!("{} requires {}", flag, expected)),
+    }
+}
+
+fn read_number<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> T {
+    match args.next().map(|value| value.parse::<T>()) {
+        Some(Ok(value)) => value,
+        _ => exit_with_error(format!("{} requires a number", flag)),
+    }
+}
+
+fn read_address(args: &mut impl Iterator<Item = String>, flag: &str) -> SocketAddr {
+    match args.next().map(|value| value.parse::<SocketAddr>()) {
+        Some(Ok(address)) => address,
+        _ => exit_with_error(format!("{} requires an address, like 0.0.0.0:14191", flag)),
+    }
+}
+
+fn read_lives(args: &mut impl Iterator<Item = String>, flag: &str) -> LivesSetting {
+    match args.next().as_deref() {
+        Some("unlimited") => LivesSetting::Unlimited,
+        Some(count) => match count.parse::<u16>() {
+            Ok(count) if count > 0 => LivesSetting::Limited(count),
+            _ => exit_with_error(format!("{} requires a positive number", flag)),
+        },
+        None => exit_with_error(format!("{} requires a number, or unlimited", flag)),
+    }
+}
+
+fn read_link_condition(args: &mut impl Iterator<Item = String>, flag: &str) -> LinkCondition {
+    match args.next().map(|value| value.parse::<LinkCondition>()) {
+        Some(Ok(link_condition)) => link_condition,
+        Some(Err(error)) => exit_with_error(format!("{}: {}", flag, error)),
+        None => exit_with_error(format!("{} requires a preset name, like poor", flag)),
+    }
+}
+
+// The name that random levels are ranked under, which is the preset name or the config file's name
+fn difficulty_name(name_or_path: &str) -> String {
+    Path::new(name_or_path)
+        .file_stem()
+        .map_or(name_or_path.to_string(), |stem| {
+            stem.to_string_lossy().into_owned()
+        })
+}
+
+fn load_levels(path: &str, is_playlist: bool) -> Vec<PlaylistLevel> {
+    let result = if is_playlist {
+        LevelPlaylist::load_playlist(path)
+    } else {
+        LevelPlaylist::load_level(path).map(|level| vec![level])
+    };
+    result.unwrap_or_else(|error| exit_with_error(format!("Failed to load {}: {}", path, error)))
+}
+
+// Reads the config file named by `--config <file>`, wherever it appears on the command line,
+// along with the directory that its paths are relative to
+fn read_config_file(args: &[String]) -> (ServerConfigFile, PathBuf) {
+    let Some(index) = args.iter().position(|arg| arg == "--config") else {
+        return (ServerConfigFile::default(), PathBuf::new());
+    };
+    let Some(path) = args.get(index + 1) else {
+        exit_with_error("--config requires a path to a config file".to_string());
+    };
+    let config = ServerConfigFile::load(path)
+        .unwrap_or_else(|error| exit_with_error(format!("Failed to load {}: {}", path, error)));
+    let directory = Path::new(path)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    (config, directory)
+}
+
+// Reads the command line, which lists level files to play in order.
+// `--config <file>` reads any of the settings below from a config file, which the flags override.
+// `--signaling-address <address>` and `--webrtc-address <address>` set where the server listens,
+// `--public-url <url>` sets the data address that clients are told to use (defaulting to
+// the WebRTC address), `--udp-address <address>` sets where native clients connect when the
+// server is built with `transport_udp`, and `--tick-ms <milliseconds>` sets how often it ticks.
+// `--link-condition <preset or latency_ms,jitter_ms,loss>` simulates a good, average, poor or
+// perfect network on packets from clients (defaulting to `CRABBER_LINK_CONDITION`, or good).
+// `--playlist <file>` adds every level listed in a playlist file,
+// `--difficulty <preset or file>` tunes random levels (defaulting to `CRABBER_DIFFICULTY`),
+// `--max-players <count>` sets how many crabs play in each room,
+// `--max-rooms <count>` caps how many rooms are open before new players spectate, and
+// `--lives <count or "unlimited">`, `--respawn-ticks <ticks>` and `--knockout-penalty <points>`
+// set what happens to knocked out crabs.
+// `--leaderboard <file>` sets where finished rounds are saved,
+// `--token <token>` only lets in players who send the same token, and
+// `--reconnect-ticks <ticks>` sets how long a dropped player's crab is kept for them.
+fn read_args() -> ServerArgs {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let (file, directory) = read_config_file(&args);
+    let relative_to_file = |path: &str| directory.join(path).to_string_lossy().into_owned();
+
+    let mut signaling_address = file.signaling_address;
+    let mut webrtc_address = file.webrtc_address;
+    let mut public_webrtc_url = file.public_webrtc_url;
+    let mut udp_address = file.udp_address;
+    let mut tick_interval_ms = file.tick_interval_ms;
+    let mut link_condition = file.link_condition;
+    let mut max_players = file.max_players.unwrap_or(DEFAULT_MAX_PLAYERS_PER_ROOM);
+    let mut max_rooms = file.max_rooms;
+    let mut respawn_config = RespawnConfig::default();
+    if let Some(lives) = file.lives {
+        respawn_config.lives = lives.to_lives();
+    }
+    if let Some(respawn_ticks) = file.respawn_ticks {
+        respawn_config.respawn_ticks = respawn_ticks;
+    }
+    if let Some(points_penalty) = file.knockout_penalty {
+        respawn_config.points_penalty = points_penalty;
+    }
+    let mut session_config = SessionConfig::default();
+    if let Some(grace_ticks) = file.reconnect_ticks {
+        session_config.grace_ticks = grace_ticks;
+    }
+    let mut auth_config = AuthConfig { token: file.token };
+    let mut leaderboard_path = file
+        .leaderboard
+        .as_deref()
+        .map_or(DEFAULT_LEADERBOARD_PATH.to_string(), relative_to_file);
+    // presets are looked up by name, so only difficulty files are relative to the config file
+    let mut difficulty = file
+        .difficulty
+        .as_deref()
+        .map(|name_or_path| match LevelGenConfig::preset(name_or_path) {
+            Some(_) => name_or_path.to_string(),
+            None => relative_to_file(name_or_path),
+        })
+        .or_else(|| std::env::var(DIFFICULTY_ENV_VAR).ok());
+
+    let mut levels = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // already read
+            "--config" => {
+                args.next();
+            }
+            "--signaling-address" => signaling_address = Some(read_address(&mut args, &arg)),
+            "--webrtc-address" => webrtc_address = Some(read_address(&mut args, &arg)),
+            "--public-url" => public_webrtc_url = Some(read_value(&mut args, &arg, "a url")),
+            "--udp-address" => udp_address = Some(read_address(&mut args, &arg)),
+            "--tick-ms" => tick_interval_ms = Some(read_number(&mut args, &arg)),
+            "--link-condition" => link_condition = Some(read_link_condition(&mut args, &arg)),
+            "--difficulty" => {
+                difficulty = Some(read_value(
+                    &mut args,
+                    &arg,
+                    "a preset name or a config file",
+                ))
+            }
+            "--leaderboard" => {
+                leaderboard_path = read_value(&mut args, &arg, "a path to a leaderboard file")
+            }
+            "--token" => auth_config.token = Some(read_value(&mut args, &arg, "a token")),
+            "--reconnect-ticks" => session_config.grace_ticks = read_number(&mut args, &arg),
+            "--max-players" => max_players = read_number(&mut args, &arg),
+            "--max-rooms" => max_rooms = Some(read_number(&mut args, &arg)),
+            "--lives" => respawn_config.lives = read_lives(&mut args, &arg).to_lives(),
+            "--respawn-ticks" => respawn_config.respawn_ticks = read_number(&mut args, &arg),
+            "--knockout-penalty" => respawn_config.points_penalty = read_number(&mut args, &arg),
+            "--playlist" => {
+                let path = read_value(&mut args, &arg, "a path to a playlist file");
+                levels.extend(load_levels(&path, true));
+            }
+            _ => levels.extend(load_levels(&arg, false)),
+        }
+    }
+    if max_players == 0 {
+        exit_with_error("--max-players requires a positive number".to_string());
+    }
+    if max_rooms == Some(0) {
+        exit_with_error("--max-rooms requires a positive number".to_string());
+    }
+    if tick_interval_ms == Some(0) {
+        exit_with_error("--tick-ms requires a positive number".to_string());
+    }
+
+    // levels on the command line replace the ones in the config file
+    if levels.is_empty() {
+        for path in file.levels.iter() {
+            levels.extend(load_levels(&relative_to_file(path), false));
+        }
+        if let Some(path) = file.playlist.as_deref() {
+            levels.extend(load_levels(&relative_to_file(path), true));
+        }
+    }
+    if levels.is_empty() {
+        levels.push(PlaylistLevel::Random);
+    }
+
+    let generator_config = match difficulty.as_deref() {
+        Some(name_or_path) => LevelGenConfig::from_preset_or_file(name_or_path),
+        None => Ok(LevelGenConfig::default()),
+    }
+    .unwrap_or_else(|error| exit_with_error(format!("Failed to load difficulty: {}", error)));
+    let difficulty = difficulty.map_or("normal".to_string(), |name_or_path| {
+        difficulty_name(&name_or_path)
+    });
+
+    let link_condition = link_condition.unwrap_or_else(|| {
+        LinkCondition::from_env().unwrap_or_else(|error| {
+            exit_with_error(format!("Failed to read link condition: {}", error))
+        })
+    });
+
+    let mut network_config = NetworkConfig::default();
+    if let Some(address) = signaling_address {
+        network_config.signaling_address = address;
+    }
+    if let Some(address) = webrtc_address {
+        network_config = NetworkConfig::new(network_config.signaling_address, address);
+    }
+    if let Some(url) = public_webrtc_url {
+        network_config.public_webrtc_url = url;
+    }
+    if let Some(address) = udp_address {
+        network_config.udp_address = address;
+    }
+
+    let leaderboard = Leaderboard::open(&leaderboard_path).unwrap_or_else(|error| {
+        exit_with_error(format!("Failed to open {}: {}", leaderboard_path, error))
+    });
+    ServerArgs {
+        tick_interval: tick_interval_ms.map_or(DEFAULT_TICK_INTERVAL, Duration::from_millis),
+        link_condition,
+        network_config,
+        playlist: LevelPlaylist::new(levels, generator_config, difficulty),
+        match_rooms: MatchRooms::new(max_players).with_max_rooms(max_rooms),
+        respawn_config,
+        leaderboard,
+        auth_config,
+        session_config,
+    }
+}
 
 fn main() {
     info!("Starting up Crabber server...");
+    let args = read_args();
 
     App::default()
         .add_plugin(TaskPoolPlugin::default())
-        .add_plugin(TypeRegistrationPlugin::default())
-        .add_plugin(FrameCountPlugin::default())
+        .add_plugin(TypeRegistrationPlugin)
+        .add_plugin(FrameCountPlugin)
         .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_millis(3)))
-        .add_plugin(ScheduleRunnerPlugin::default())
+        .add_plugin(ScheduleRunnerPlugin)
         .add_plugin(LogPlugin::default())
-        .add_plugin(CrabberServerPlugin)
+        .insert_resource(args.network_config)
+        .insert_resource(args.playlist)
+        .insert_resource(args.match_rooms)
+        .insert_resource(args.respawn_config)
+        .insert_resource(args.leaderboard)
+        .insert_resource(args.auth_config)
+        .insert_resource(args.session_config)
+        .add_plugin(CrabberServerPlugin::new(
+            args.tick_interval,
+            args.link_condition,
+        ))
         .run();
 }
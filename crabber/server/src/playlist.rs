@@ -0,0 +1,126 @@
+use std::path::{Path, PathBuf};
+
+use bevy_ecs::prelude::Resource;
+use bevy_log::{info, warn};
+use serde::Deserialize;
+
+use crabber_protocol::{
+    components::Level,
+    generation::LevelGenConfig,
+    level_file::{LevelFile, LevelFileError},
+};
+
+// An entry of a playlist file, which is a RON list such as:
+//
+// [File("levels/first.ron"), File("levels/second.ron"), Random, Endless]
+//
+// File paths are relative to the playlist file.
+#[derive(Deserialize)]
+enum PlaylistFileEntry {
+    Random,
+    Endless,
+    File(PathBuf),
+}
+
+pub enum PlaylistLevel {
+    Random,
+    Endless,
+    Authored { name: String, level_file: LevelFile },
+}
+
+// The levels that the server plays through, in order, looping back to the start
+#[derive(Resource)]
+pub struct LevelPlaylist {
+    levels: Vec<PlaylistLevel>,
+    next_index: usize,
+    // the tuning used for random and endless levels, and the name it is known by
+    generator_config: LevelGenConfig,
+    difficulty: String,
+}
+
+impl Default for LevelPlaylist {
+    fn default() -> Self {
+        LevelPlaylist::new(
+            vec![PlaylistLevel::Random],
+            LevelGenConfig::default(),
+            "normal".to_string(),
+        )
+    }
+}
+
+impl LevelPlaylist {
+    pub fn new(
+        levels: Vec<PlaylistLevel>,
+        generator_config: LevelGenConfig,
+        difficulty: String,
+    ) -> Self {
+        LevelPlaylist {
+            levels,
+            next_index: 0,
+            generator_config,
+            difficulty,
+        }
+    }
+
+    // Loads a single level file, checking that it describes a playable level.
+    // The level is named after its file.
+    pub fn load_level(path: impl AsRef<Path>) -> Result<PlaylistLevel, LevelFileError> {
+        let path = path.as_ref();
+        let level_file = LevelFile::load(path)?;
+        level_file.to_level()?;
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(PlaylistLevel::Authored { name, level_file })
+    }
+
+    // Loads every level listed in a playlist file
+    pub fn load_playlist(path: impl AsRef<Path>) -> Result<Vec<PlaylistLevel>, LevelFileError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(LevelFileError::Io)?;
+        let entries: Vec<PlaylistFileEntry> =
+            ron::from_str(&source).map_err(LevelFileError::Parse)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        entries
+            .into_iter()
+            .map(|entry| match entry {
+                PlaylistFileEntry::Random => Ok(PlaylistLevel::Random),
+                PlaylistFileEntry::Endless => Ok(PlaylistLevel::Endless),
+                PlaylistFileEntry::File(level_path) => {
+                    LevelPlaylist::load_level(directory.join(level_path))
+                }
+            })
+            .collect()
+    }
+
+    // Picks the next level to play, along with the name of the leaderboard that its scores go on
+    pub fn next_level(&mut self) -> (Level, String) {
+        let (level, leaderboard) = match self.levels.get(self.next_index) {
+            Some(PlaylistLevel::Authored { name, level_file }) => {
+                info!("Playing level {} of the playlist", self.next_index + 1);
+                let level = level_file
+                    .to_level()
+                    .expect("playlist levels are checked when they are loaded");
+                (level, format!("level/{}", name))
+            }
+            Some(PlaylistLevel::Endless) => (
+                Level::new_random_endless_with_config(self.generator_config.clone()),
+                format!("endless/{}", self.difficulty),
+            ),
+            Some(PlaylistLevel::Random) | None => {
+                let level = Level::new_random_with_config(self.generator_config.clone())
+                    .unwrap_or_else(|unsolvable| {
+                        // a round on a hard level beats no round at all
+                        warn!("Playing a level that may not be solvable: {}", unsolvable);
+                        *unsolvable.level
+                    });
+                (level, format!("random/{}", self.difficulty))
+            }
+        };
+        if !self.levels.is_empty() {
+            self.next_index = (self.next_index + 1) % self.levels.len();
+        }
+        (level, leaderboard)
+    }
+}
@@ -0,0 +1,214 @@
+use bevy_ecs::{entity::Entity, prelude::Resource};
+use bevy_utils::{HashMap, HashSet};
+
+use naia_bevy_server::{RoomKey, UserKey};
+
+use crabber_protocol::messages::{LobbyPlayer, LobbyStateMessage};
+
+use crate::UserEntities;
+
+// How many crabs play together in a single room, unless the server is configured otherwise
+pub const DEFAULT_MAX_PLAYERS_PER_ROOM: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoomPhase {
+    // players are gathering and readying up, and there is no level yet
+    Lobby,
+    // the level is spawned and crabs are playing on it
+    Playing,
+}
+
+// A single match: the users playing together, and the level they play on once it starts
+pub struct MatchRoom {
+    pub phase: RoomPhase,
+    pub level: Option<Entity>,
+    // the leaderboard that scores on the current level are ranked against
+    pub leaderboard: String,
+    // whether the current round has been saved to the leaderboard
+    pub is_round_recorded: bool,
+    pub players: Vec<UserKey>,
+    // users watching the match, who take the place of players that leave
+    pub spectators: Vec<UserKey>,
+    // slots kept for disconnected players, until they reconnect or their grace period runs out
+    pub reserved_slots: usize,
+    pub ready: HashSet<UserKey>,
+    // the ticks left before the match starts, once any player is ready
+    pub countdown_ticks: Option<u16>,
+    // whether the lobby has changed since it was last sent to players
+    pub lobby_changed: bool,
+}
+
+impl MatchRoom {
+    fn new() -> Self {
+        MatchRoom {
+            phase: RoomPhase::Lobby,
+            level: None,
+            leaderboard: String::new(),
+            is_round_recorded: false,
+            players: Vec::new(),
+            spectators: Vec::new(),
+            reserved_slots: 0,
+            ready: HashSet::default(),
+            countdown_ticks: None,
+            lobby_changed: true,
+        }
+    }
+
+    pub fn has_open_slots(&self, max_players: usize) -> bool {
+        self.phase == RoomPhase::Lobby && self.players.len() + self.reserved_slots < max_players
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty() && self.spectators.is_empty() && self.reserved_slots == 0
+    }
+
+    pub fn is_everyone_ready(&self) -> bool {
+        !self.players.is_empty() && self.players.iter().all(|user| self.ready.contains(user))
+    }
+
+    pub fn lobby_state_message(&self, user_entities: &UserEntities) -> LobbyStateMessage {
+        let players = self
+            .players
+            .iter()
+            .map(|user_key| LobbyPlayer {
+                name: user_entities
+                    .get_name(user_key)
+                    .cloned()
+                    .unwrap_or_default(),
+                ready: self.ready.contains(user_key),
+            })
+            .collect();
+        LobbyStateMessage::new(players, self.countdown_ticks)
+    }
+}
+
+#[derive(Resource)]
+pub struct MatchRooms {
+    max_players: usize,
+    // once this many rooms are open, new users spectate instead of starting another room
+    max_rooms: Option<usize>,
+    rooms: HashMap<RoomKey, MatchRoom>,
+    // the room of every player and spectator
+    user_to_room_map: HashMap<UserKey, RoomKey>,
+}
+
+impl Default for MatchRooms {
+    fn default() -> Self {
+        MatchRooms::new(DEFAULT_MAX_PLAYERS_PER_ROOM)
+    }
+}
+
+impl MatchRooms {
+    pub fn new(max_players: usize) -> Self {
+        MatchRooms {
+            max_players: max_players.max(1),
+            max_rooms: None,
+            rooms: HashMap::default(),
+            user_to_room_map: HashMap::default(),
+        }
+    }
+
+    pub fn with_max_rooms(mut self, max_rooms: Option<usize>) -> Self {
+        self.max_rooms = max_rooms.map(|max_rooms| max_rooms.max(1));
+        self
+    }
+
+    pub fn max_players(&self) -> usize {
+        self.max_players
+    }
+
+    pub fn can_make_room(&self) -> bool {
+        self.max_rooms
+            .is_none_or(|max_rooms| self.rooms.len() < max_rooms)
+    }
+
+    // Picks the fullest lobby that still has a slot open, so that rooms fill up before new ones start
+    pub fn find_open_room(&self) -> Option<RoomKey> {
+        self.rooms
+            .iter()
+            .filter(|(_, room)| room.has_open_slots(self.max_players))
+            .max_by_key(|(_, room)| room.players.len())
+            .map(|(room_key, _)| *room_key)
+    }
+
+    // Picks the match with the most players to watch, preferring ones that have started
+    pub fn find_room_to_spectate(&self) -> Option<RoomKey> {
+        self.rooms
+            .iter()
+            .max_by_key(|(_, room)| (room.phase == RoomPhase::Playing, room.players.len()))
+            .map(|(room_key, _)| *room_key)
+    }
+
+    pub fn insert_room(&mut self, room_key: RoomKey) {
+        self.rooms.insert(room_key, MatchRoom::new());
+    }
+
+    pub fn remove_room(&mut self, room_key: &RoomKey) -> Option<MatchRoom> {
+        self.rooms.remove(room_key).inspect(|room| {
+            for user_key in room.players.iter().chain(room.spectators.iter()) {
+                self.user_to_room_map.remove(user_key);
+            }
+        })
+    }
+
+    pub fn get(&self, room_key: &RoomKey) -> Option<&MatchRoom> {
+        self.rooms.get(room_key)
+    }
+
+    pub fn get_mut(&mut self, room_key: &RoomKey) -> Option<&mut MatchRoom> {
+        self.rooms.get_mut(room_key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&RoomKey, &MatchRoom)> {
+        self.rooms.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&RoomKey, &mut MatchRoom)> {
+        self.rooms.iter_mut()
+    }
+
+    pub fn get_user_room(&self, user_key: &UserKey) -> Option<&RoomKey> {
+        self.user_to_room_map.get(user_key)
+    }
+
+    pub fn add_player(&mut self, room_key: RoomKey, user_key: UserKey) {
+        if let Some(room) = self.rooms.get_mut(&room_key) {
+            room.players.push(user_key);
+            room.lobby_changed = true;
+            self.user_to_room_map.insert(user_key, room_key);
+        }
+    }
+
+    pub fn add_spectator(&mut self, room_key: RoomKey, user_key: UserKey) {
+        if let Some(room) = self.rooms.get_mut(&room_key) {
+            room.spectators.push(user_key);
+            self.user_to_room_map.insert(user_key, room_key);
+        }
+    }
+
+    // Moves spectators into any player slots left open in a room, returning the promoted users
+    pub fn promote_spectators(&mut self, room_key: &RoomKey) -> Vec<UserKey> {
+        let Some(room) = self.rooms.get_mut(room_key) else { return Vec::new() };
+        let open_slots = self
+            .max_players
+            .saturating_sub(room.players.len() + room.reserved_slots);
+        let promoted = room
+            .spectators
+            .drain(..open_slots.min(room.spectators.len()))
+            .collect::<Vec<_>>();
+        room.players.extend(promoted.iter().copied());
+        promoted
+    }
+
+    // Removes a player or spectator from their room, returning the room they were in
+    pub fn remove_player(&mut self, user_key: &UserKey) -> Option<RoomKey> {
+        let room_key = self.user_to_room_map.remove(user_key)?;
+        if let Some(room) = self.rooms.get_mut(&room_key) {
+            room.players.retain(|player| player != user_key);
+            room.spectators.retain(|spectator| spectator != user_key);
+            room.ready.remove(user_key);
+            room.lobby_changed = true;
+        }
+        Some(room_key)
+    }
+}
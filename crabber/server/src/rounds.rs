@@ -0,0 +1,129 @@
+use bevy_ecs::{
+    entity::Entity,
+    query::Without,
+    system::{Commands, Query, Res, ResMut},
+};
+use bevy_log::info;
+
+use naia_bevy_server::{CommandsExt, RoomKey, Server};
+
+use crabber_protocol::components::{
+    Controlled, Crab, Level, MatchState, OnLevel, RespawnConfig, RespawnRule,
+};
+
+use crate::{
+    playlist::LevelPlaylist,
+    rooms::{MatchRooms, RoomPhase},
+    sessions::Sessions,
+    UserEntities,
+};
+
+// Spawns a level and its obstacles into a room, with a fresh round to play on it
+pub fn spawn_level(
+    commands: &mut Commands,
+    server: &mut Server,
+    room_key: &RoomKey,
+    level: Level,
+    round: u16,
+    respawn_config: RespawnConfig,
+) -> Entity {
+    let level_entity = commands.spawn_empty().id();
+    // obstacles are not replicated: clients rebuild them from the level seed
+    let (car_bundles, raft_bundles) = level.create_level_bundles();
+    for bundle in car_bundles.into_iter() {
+        commands.spawn((bundle, OnLevel(level_entity), Controlled));
+    }
+    for bundle in raft_bundles.into_iter() {
+        commands.spawn((bundle, OnLevel(level_entity), Controlled));
+    }
+    commands
+        .entity(level_entity)
+        .insert((
+            level,
+            MatchState::new(round),
+            RespawnRule::new(respawn_config),
+            Controlled,
+        ))
+        .enable_replication(server);
+    server.room_mut(room_key).add_entity(&level_entity);
+    level_entity
+}
+
+// Despawns a level along with its obstacles
+pub fn despawn_level(
+    commands: &mut Commands,
+    level_entity: Entity,
+    obstacles_query: &Query<(Entity, &OnLevel), Without<Crab>>,
+) {
+    for (obstacle, OnLevel(level)) in obstacles_query.iter() {
+        if *level == level_entity {
+            commands.entity(obstacle).despawn();
+        }
+    }
+    commands.entity(level_entity).despawn();
+}
+
+// Replaces the level of every room whose results have been shown, so that its players
+// get new crabs on a fresh level
+#[allow(clippy::too_many_arguments)]
+pub fn start_next_rounds(
+    mut commands: Commands,
+    mut server: Server,
+    mut match_rooms: ResMut<MatchRooms>,
+    mut user_entities: ResMut<UserEntities>,
+    mut playlist: ResMut<LevelPlaylist>,
+    mut sessions: ResMut<Sessions>,
+    respawn_config: Res<RespawnConfig>,
+    level_query: Query<&MatchState>,
+    obstacles_query: Query<(Entity, &OnLevel), Without<Crab>>,
+) {
+    let finished_rooms = match_rooms
+        .iter()
+        .filter(|(_, room)| room.phase == RoomPhase::Playing)
+        .filter_map(|(room_key, room)| {
+            let level_entity = room.level?;
+            let match_state = level_query.get(level_entity).ok()?;
+            match_state
+                .is_finished()
+                .then_some((*room_key, level_entity, *match_state.round))
+        })
+        .collect::<Vec<_>>();
+
+    for (room_key, level_entity, round) in finished_rooms {
+        // spectators get to play in the next round, if there is room for them
+        match_rooms.promote_spectators(&room_key);
+        let Some(room) = match_rooms.get_mut(&room_key) else { continue };
+
+        for user_key in room.players.iter() {
+            if let Some(entity) = user_entities.remove(user_key) {
+                server.room_mut(&room_key).remove_entity(&entity);
+                commands.entity(entity).despawn();
+            }
+        }
+        // players who are still reconnecting get a new crab when they return
+        for entity in sessions.release_crabs(&room_key) {
+            server.room_mut(&room_key).remove_entity(&entity);
+            commands.entity(entity).despawn();
+        }
+        server.room_mut(&room_key).remove_entity(&level_entity);
+        despawn_level(&mut commands, level_entity, &obstacles_query);
+
+        let (level, leaderboard) = playlist.next_level();
+        info!(
+            "Starting round {} for {} players with level seed {}",
+            round + 1,
+            room.players.len(),
+            *level.seed
+        );
+        room.level = Some(spawn_level(
+            &mut commands,
+            &mut server,
+            &room_key,
+            level,
+            round.wrapping_add(1),
+            *respawn_config,
+        ));
+        room.leaderboard = leaderboard;
+        room.is_round_recorded = false;
+    }
+}
@@ -0,0 +1,179 @@
+use bevy_ecs::{
+    entity::Entity,
+    event::EventReader,
+    prelude::Resource,
+    query::Without,
+    system::{Commands, Query, ResMut},
+};
+use bevy_log::info;
+use bevy_utils::HashMap;
+
+use naia_bevy_server::{events::TickEvent, RoomKey, Server, UserKey};
+
+use crabber_protocol::components::{Crab, OnLevel};
+
+use crate::{
+    connection::{close_room_if_empty, promote_spectators},
+    rooms::MatchRooms,
+};
+
+// How long a disconnected player's crab waits for them to come back (about 10 seconds),
+// unless the server is configured otherwise
+pub const DEFAULT_RECONNECT_GRACE_TICKS: u16 = 600;
+
+#[derive(Resource)]
+pub struct SessionConfig {
+    // no crabs are held when this is zero
+    pub grace_ticks: u16,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            grace_ticks: DEFAULT_RECONNECT_GRACE_TICKS,
+        }
+    }
+}
+
+// A player who lost their connection mid-match, along with the crab kept for them
+pub struct HeldPlayer {
+    pub name: String,
+    pub room_key: RoomKey,
+    // the crab is let go if the round ends before the player returns
+    pub entity: Option<Entity>,
+    remaining_ticks: u16,
+}
+
+// The session tokens given to players, which let them take back their crab after a dropped
+// connection
+#[derive(Resource, Default)]
+pub struct Sessions {
+    user_to_token_map: HashMap<UserKey, String>,
+    held_players: HashMap<String, HeldPlayer>,
+    // users who sent the token of a held player, and who are yet to finish connecting
+    reconnecting_users: HashMap<UserKey, String>,
+}
+
+impl Sessions {
+    // The user's session token, which is made when they are first given a crab
+    pub fn token_for(&mut self, user_key: &UserKey) -> String {
+        self.user_to_token_map
+            .entry(*user_key)
+            .or_insert_with(|| format!("{:032x}", rand::random::<u128>()))
+            .clone()
+    }
+
+    // Keeps a disconnected player's crab and name for them, if they have a session to return to
+    pub fn hold(
+        &mut self,
+        user_key: &UserKey,
+        name: String,
+        room_key: RoomKey,
+        entity: Entity,
+        grace_ticks: u16,
+    ) -> bool {
+        if grace_ticks == 0 {
+            return false;
+        }
+        let Some(token) = self.user_to_token_map.remove(user_key) else { return false };
+        self.held_players.insert(
+            token,
+            HeldPlayer {
+                name,
+                room_key,
+                entity: Some(entity),
+                remaining_ticks: grace_ticks,
+            },
+        );
+        true
+    }
+
+    pub fn held_name(&self, token: &str) -> Option<&String> {
+        self.held_players.get(token).map(|held| &held.name)
+    }
+
+    pub fn is_name_held(&self, name: &str) -> bool {
+        self.held_players.values().any(|held| held.name == name)
+    }
+
+    // Whether another user has already sent this token and is on their way back
+    pub fn is_claimed(&self, token: &str) -> bool {
+        self.reconnecting_users
+            .values()
+            .any(|claimed| claimed == token)
+    }
+
+    // Marks a user as returning to a held player, once they have sent its token
+    pub fn claim(&mut self, user_key: UserKey, token: String) {
+        self.reconnecting_users.insert(user_key, token);
+    }
+
+    // Hands a held player back to the user who claimed it, once they have connected
+    pub fn take_reconnect(&mut self, user_key: &UserKey) -> Option<HeldPlayer> {
+        let token = self.reconnecting_users.remove(user_key)?;
+        let held = self.held_players.remove(&token)?;
+        self.user_to_token_map.insert(*user_key, token);
+        Some(held)
+    }
+
+    // Lets go of the crabs held in a room, which happens when its round ends
+    pub fn release_crabs(&mut self, room_key: &RoomKey) -> Vec<Entity> {
+        self.held_players
+            .values_mut()
+            .filter(|held| held.room_key == *room_key)
+            .filter_map(|held| held.entity.take())
+            .collect()
+    }
+
+    pub fn remove_user(&mut self, user_key: &UserKey) {
+        self.user_to_token_map.remove(user_key);
+        self.reconnecting_users.remove(user_key);
+    }
+
+    fn tick_held_players(&mut self) -> Vec<HeldPlayer> {
+        for held in self.held_players.values_mut() {
+            held.remaining_ticks = held.remaining_ticks.saturating_sub(1);
+        }
+        let expired_tokens = self
+            .held_players
+            .iter()
+            .filter(|(_, held)| held.remaining_ticks == 0)
+            .map(|(token, _)| token.clone())
+            .collect::<Vec<_>>();
+        expired_tokens
+            .iter()
+            .filter_map(|token| self.held_players.remove(token))
+            .collect()
+    }
+}
+
+// Gives up on disconnected players whose grace period has run out, freeing their slots
+pub fn expire_held_players(
+    mut commands: Commands,
+    mut server: Server,
+    mut tick_reader: EventReader<TickEvent>,
+    mut sessions: ResMut<Sessions>,
+    mut match_rooms: ResMut<MatchRooms>,
+    obstacles_query: Query<(Entity, &OnLevel), Without<Crab>>,
+) {
+    for _ in tick_reader.iter() {
+        for held in sessions.tick_held_players() {
+            info!("{} did not reconnect in time", held.name);
+            if let Some(entity) = held.entity {
+                server.room_mut(&held.room_key).remove_entity(&entity);
+                commands.entity(entity).despawn();
+            }
+            let Some(room) = match_rooms.get_mut(&held.room_key) else { continue };
+            room.reserved_slots = room.reserved_slots.saturating_sub(1);
+            room.lobby_changed = true;
+            promote_spectators(&mut server, &mut match_rooms, &held.room_key);
+            close_room_if_empty(
+                &mut commands,
+                &mut server,
+                &mut match_rooms,
+                &held.room_key,
+                &obstacles_query,
+            );
+        }
+    }
+}
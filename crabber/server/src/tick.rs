@@ -1,11 +1,26 @@
-use bevy_ecs::event::EventReader;
+use bevy_ecs::{
+    event::EventReader,
+    query::With,
+    system::{Query, Res},
+};
 
-use naia_bevy_server::{events::TickEvent, Server};
+use naia_bevy_server::{
+    events::{MessageEvents, TickEvent},
+    Server,
+};
 
-use crabber_protocol::{channels::PlayerInputChannel, messages::InputMessage};
+use crabber_protocol::{
+    channels::{LevelChecksumChannel, PlayerInputChannel, RelayedInputChannel},
+    components::{
+        obstacle_checksum, ConstantMotor, Controlled, Level, MotorOrigin, OnLevel, TileRow,
+    },
+    messages::{InputMessage, LevelChecksumMessage, RelayedInputMessage},
+};
 
 use crabber_core::{EntityActionMap, TickActions};
 
+use crate::{rooms::MatchRooms, UserEntities};
+
 pub fn tick_events(
     mut server: Server,
     mut tick_reader: EventReader<TickEvent>,
@@ -27,6 +42,37 @@ pub fn tick_events(
     tick_actions
 }
 
+// Passes players' inputs on to everyone else in their room as they arrive, which is ahead of the
+// ticks that they are for, along with the crab that the sender plays
+pub fn relay_inputs(
+    mut server: Server,
+    mut event_reader: EventReader<MessageEvents>,
+    match_rooms: Res<MatchRooms>,
+    user_entities: Res<UserEntities>,
+) {
+    for events in event_reader.iter() {
+        for (user_key, message) in events.read::<RelayedInputChannel, RelayedInputMessage>() {
+            let Some(entity) = user_entities.get_entity(&user_key) else { continue };
+            let Some(room) = match_rooms
+                .get_user_room(&user_key)
+                .and_then(|room_key| match_rooms.get(room_key))
+            else {
+                continue;
+            };
+            let mut relayed_message = RelayedInputMessage::new(message.tick, message.action);
+            relayed_message.entity.set(&server, entity);
+            for other_user_key in room.players.iter().chain(room.spectators.iter()) {
+                if *other_user_key != user_key {
+                    server.send_message::<RelayedInputChannel, RelayedInputMessage>(
+                        other_user_key,
+                        &relayed_message,
+                    );
+                }
+            }
+        }
+    }
+}
+
 pub fn update_entity_scopes(mut server: Server, mut tick_reader: EventReader<TickEvent>) {
     if !tick_reader.iter().count() != 0 {
         // Update entity scopes
@@ -35,3 +81,39 @@ pub fn update_entity_scopes(mut server: Server, mut tick_reader: EventReader<Tic
         }
     }
 }
+
+// how many ticks pass between each level checksum broadcast
+const CHECKSUM_INTERVAL_TICKS: u16 = 64;
+
+pub fn send_level_checksums(
+    mut server: Server,
+    mut tick_reader: EventReader<TickEvent>,
+    match_rooms: Res<MatchRooms>,
+    level_query: Query<&Level>,
+    obstacles_query: Query<(&ConstantMotor, &MotorOrigin, &OnLevel), With<Controlled>>,
+) {
+    for TickEvent(server_tick) in tick_reader.iter() {
+        if *server_tick % CHECKSUM_INTERVAL_TICKS != 0 {
+            continue;
+        }
+        for (room_key, room) in match_rooms.iter() {
+            let Some(level_entity) = room.level else { continue };
+            let Ok(level) = level_query.get(level_entity) else { continue };
+            let TileRow(end_row) = level.end_row();
+            let obstacles = obstacles_query
+                .iter()
+                .filter(|(_, origin, OnLevel(obstacle_level))| {
+                    let TileRow(row) = level.y_to_row(origin.y);
+                    *obstacle_level == level_entity && row >= *level.first_row && row < end_row
+                })
+                .map(|(motor, origin, _)| (motor, origin));
+            let checksum = obstacle_checksum(obstacles, level, *server_tick);
+            let mut checksum_message =
+                LevelChecksumMessage::new(*server_tick, *level.first_row, end_row, checksum);
+            checksum_message.level.set(&server, &level_entity);
+            server
+                .room_mut(room_key)
+                .broadcast_message::<LevelChecksumChannel, LevelChecksumMessage>(&checksum_message);
+        }
+    }
+}
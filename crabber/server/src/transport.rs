@@ -0,0 +1,135 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use bevy_ecs::{
+    event::EventReader,
+    system::{Res, Resource},
+};
+use bevy_utils::HashMap;
+
+use naia_bevy_server::{
+    events::DisconnectEvent,
+    transport::{udp, webrtc, PacketReceiver, PacketSender, RecvError, SendError, Socket},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Transport {
+    WebRtc,
+    Udp,
+}
+
+// The transport that each client was last heard from over, shared between the sender, the
+// receiver and the server, which forgets clients once they disconnect
+#[derive(Resource, Clone, Default)]
+pub struct TransportAddresses(Arc<Mutex<HashMap<SocketAddr, Transport>>>);
+
+impl TransportAddresses {
+    fn get(&self, address: &SocketAddr) -> Option<Transport> {
+        let addresses = self.0.lock().ok()?;
+        addresses.get(address).copied()
+    }
+
+    fn heard_from(&self, address: SocketAddr, transport: Transport) {
+        if let Ok(mut addresses) = self.0.lock() {
+            addresses.insert(address, transport);
+        }
+    }
+
+    pub fn forget(&self, address: &SocketAddr) {
+        if let Ok(mut addresses) = self.0.lock() {
+            addresses.remove(address);
+        }
+    }
+}
+
+// Listens over WebRTC and plain UDP at once, so that browser and native players share matches.
+// Packets are sent back over whichever transport their address was last heard from.
+pub struct DualSocket {
+    webrtc: webrtc::Socket,
+    udp: udp::Socket,
+    addresses: TransportAddresses,
+}
+
+impl DualSocket {
+    pub fn new(webrtc: webrtc::Socket, udp: udp::Socket) -> Self {
+        DualSocket {
+            webrtc,
+            udp,
+            addresses: TransportAddresses::default(),
+        }
+    }
+
+    pub fn addresses(&self) -> TransportAddresses {
+        self.addresses.clone()
+    }
+}
+
+impl From<DualSocket> for Box<dyn Socket> {
+    fn from(socket: DualSocket) -> Self {
+        Box::new(socket)
+    }
+}
+
+impl Socket for DualSocket {
+    fn listen(self: Box<Self>) -> (Box<dyn PacketSender>, Box<dyn PacketReceiver>) {
+        let (webrtc_sender, webrtc_receiver) = Box::new(self.webrtc).listen();
+        let (udp_sender, udp_receiver) = Box::new(self.udp).listen();
+        let sender = DualPacketSender {
+            webrtc: webrtc_sender,
+            udp: udp_sender,
+            addresses: self.addresses.clone(),
+        };
+        let receiver = DualPacketReceiver {
+            webrtc: webrtc_receiver,
+            udp: udp_receiver,
+            addresses: self.addresses,
+        };
+        (Box::new(sender), Box::new(receiver))
+    }
+}
+
+struct DualPacketSender {
+    webrtc: Box<dyn PacketSender>,
+    udp: Box<dyn PacketSender>,
+    addresses: TransportAddresses,
+}
+
+impl PacketSender for DualPacketSender {
+    fn send(&self, address: &SocketAddr, payload: &[u8]) -> Result<(), SendError> {
+        match self.addresses.get(address) {
+            Some(Transport::Udp) => self.udp.send(address, payload),
+            Some(Transport::WebRtc) | None => self.webrtc.send(address, payload),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct DualPacketReceiver {
+    webrtc: Box<dyn PacketReceiver>,
+    udp: Box<dyn PacketReceiver>,
+    addresses: TransportAddresses,
+}
+
+impl PacketReceiver for DualPacketReceiver {
+    fn receive(&mut self) -> Result<Option<(SocketAddr, &[u8])>, RecvError> {
+        if let Some((address, payload)) = self.webrtc.receive()? {
+            self.addresses.heard_from(address, Transport::WebRtc);
+            return Ok(Some((address, payload)));
+        }
+        let Some((address, payload)) = self.udp.receive()? else { return Ok(None) };
+        self.addresses.heard_from(address, Transport::Udp);
+        Ok(Some((address, payload)))
+    }
+}
+
+pub fn forget_disconnected_addresses(
+    addresses: Option<Res<TransportAddresses>>,
+    mut event_reader: EventReader<DisconnectEvent>,
+) {
+    let Some(addresses) = addresses else { return };
+    for DisconnectEvent(_, user) in event_reader.iter() {
+        addresses.forget(&user.address);
+    }
+}
@@ -36,7 +36,8 @@ impl<A> Test<A> {
         .add_system(bevy::window::close_on_esc);
 
         (self.setup_graphics)(&mut app);
-        (self.setup)(&mut app);
+        let state = (self.setup)(&mut app);
         app.run();
+        assert!((self.check)(&app, state), "{} failed its check", self.label);
     }
 }
@@ -0,0 +1,227 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+
+use naia_bevy_client::transport as client;
+use naia_bevy_server::transport as server;
+
+// The address that clients see the server at. Nothing is bound to it, it only tells peers apart.
+pub const SERVER_ADDRESS: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 1);
+
+// Packets waiting to be received, for the server and for each connected client
+#[derive(Default)]
+struct Mailboxes {
+    // along with the address of the client that sent each one
+    server: VecDeque<(SocketAddr, Vec<u8>)>,
+    clients: HashMap<SocketAddr, VecDeque<Vec<u8>>>,
+    next_client_port: u16,
+}
+
+// An in-memory network that connects a server and its clients inside one process.
+// Packets are delivered in order and are never dropped, and arrive as soon as they are sent.
+#[derive(Clone, Default)]
+pub struct LoopbackNetwork {
+    mailboxes: Arc<Mutex<Mailboxes>>,
+}
+
+impl LoopbackNetwork {
+    pub fn new() -> Self {
+        LoopbackNetwork::default()
+    }
+
+    pub fn server_socket(&self) -> ServerSocket {
+        ServerSocket {
+            network: self.clone(),
+        }
+    }
+
+    // Makes a socket for a new client, which is given its own address.
+    // Ports wrap around, skipping the server's and any that a client still has.
+    pub fn client_socket(&self) -> ClientSocket {
+        let mut mailboxes = self.lock();
+        let address = loop {
+            mailboxes.next_client_port = mailboxes.next_client_port.wrapping_add(1);
+            let address = SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::LOCALHOST),
+                SERVER_ADDRESS
+                    .port()
+                    .wrapping_add(mailboxes.next_client_port),
+            );
+            if address != SERVER_ADDRESS && !mailboxes.clients.contains_key(&address) {
+                break address;
+            }
+        };
+        mailboxes.clients.insert(address, VecDeque::new());
+        ClientSocket {
+            mailbox: Arc::new(ClientMailbox {
+                network: self.clone(),
+                address,
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Mailboxes> {
+        // a test that panicked while holding the lock has failed already
+        self.mailboxes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// The server's end of a `LoopbackNetwork`
+pub struct ServerSocket {
+    network: LoopbackNetwork,
+}
+
+impl From<ServerSocket> for Box<dyn server::Socket> {
+    fn from(socket: ServerSocket) -> Self {
+        Box::new(socket)
+    }
+}
+
+impl server::Socket for ServerSocket {
+    fn listen(
+        self: Box<Self>,
+    ) -> (
+        Box<dyn server::PacketSender>,
+        Box<dyn server::PacketReceiver>,
+    ) {
+        let sender = ServerPacketSender {
+            network: self.network.clone(),
+        };
+        let receiver = ServerPacketReceiver {
+            network: self.network,
+            address: SERVER_ADDRESS,
+            buffer: Vec::new(),
+        };
+        (Box::new(sender), Box::new(receiver))
+    }
+}
+
+struct ServerPacketSender {
+    network: LoopbackNetwork,
+}
+
+impl server::PacketSender for ServerPacketSender {
+    fn send(&self, address: &SocketAddr, payload: &[u8]) -> Result<(), server::SendError> {
+        // like UDP, packets to clients that are not listening are lost
+        if let Some(mailbox) = self.network.lock().clients.get_mut(address) {
+            mailbox.push_back(payload.to_vec());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct ServerPacketReceiver {
+    network: LoopbackNetwork,
+    // the sender of the packet in `buffer`
+    address: SocketAddr,
+    buffer: Vec<u8>,
+}
+
+impl server::PacketReceiver for ServerPacketReceiver {
+    fn receive(&mut self) -> Result<Option<(SocketAddr, &[u8])>, server::RecvError> {
+        let Some((address, payload)) = self.network.lock().server.pop_front() else {
+            return Ok(None);
+        };
+        self.address = address;
+        self.buffer = payload;
+        Ok(Some((self.address, &self.buffer)))
+    }
+}
+
+// A client's mailbox, which is removed from the network once every end of the client that
+// shares it has been dropped
+struct ClientMailbox {
+    network: LoopbackNetwork,
+    address: SocketAddr,
+}
+
+impl Drop for ClientMailbox {
+    fn drop(&mut self) {
+        self.network.lock().clients.remove(&self.address);
+    }
+}
+
+// A client's end of a `LoopbackNetwork`
+pub struct ClientSocket {
+    mailbox: Arc<ClientMailbox>,
+}
+
+impl ClientSocket {
+    pub fn address(&self) -> SocketAddr {
+        self.mailbox.address
+    }
+}
+
+impl From<ClientSocket> for Box<dyn client::Socket> {
+    fn from(socket: ClientSocket) -> Self {
+        Box::new(socket)
+    }
+}
+
+impl client::Socket for ClientSocket {
+    fn connect(
+        self: Box<Self>,
+    ) -> (
+        Box<dyn client::PacketSender>,
+        Box<dyn client::PacketReceiver>,
+    ) {
+        let sender = ClientPacketSender {
+            mailbox: self.mailbox.clone(),
+        };
+        let receiver = ClientPacketReceiver {
+            mailbox: self.mailbox,
+            buffer: Vec::new(),
+        };
+        (Box::new(sender), Box::new(receiver))
+    }
+}
+
+struct ClientPacketSender {
+    mailbox: Arc<ClientMailbox>,
+}
+
+impl client::PacketSender for ClientPacketSender {
+    fn send(&self, payload: &[u8]) -> Result<(), client::SendError> {
+        self.mailbox
+            .network
+            .lock()
+            .server
+            .push_back((self.mailbox.address, payload.to_vec()));
+        Ok(())
+    }
+
+    fn server_addr(&self) -> client::ServerAddr {
+        client::ServerAddr::Found(SERVER_ADDRESS)
+    }
+}
+
+#[derive(Clone)]
+struct ClientPacketReceiver {
+    mailbox: Arc<ClientMailbox>,
+    buffer: Vec<u8>,
+}
+
+impl client::PacketReceiver for ClientPacketReceiver {
+    fn receive(&mut self) -> Result<Option<&[u8]>, client::RecvError> {
+        let mut mailboxes = self.mailbox.network.lock();
+        let Some(payload) = mailboxes
+            .clients
+            .get_mut(&self.mailbox.address)
+            .and_then(VecDeque::pop_front)
+        else {
+            return Ok(None);
+        };
+        drop(mailboxes);
+        self.buffer = payload;
+        Ok(Some(&self.buffer))
+    }
+
+    fn server_addr(&self) -> client::ServerAddr {
+        client::ServerAddr::Found(SERVER_ADDRESS)
+    }
+}